@@ -0,0 +1,58 @@
+//! Redirects of short and long slugs, with and without snapshots, and the
+//! read-only lookup a serving layer does instead: `cargo bench --bench
+//! redirect`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::hint::black_box;
+use url_shortener::commands::CommandHandler;
+use url_shortener::{LookupLeniency, Slug, Url, UrlShortenerService};
+
+/// A service holding `links` links, with the one under `slug` last.
+fn service(slug: &Slug, links: usize, snapshot_interval: usize) -> UrlShortenerService {
+    let leniency = LookupLeniency { case_insensitive: true, ..LookupLeniency::default() };
+    let mut service = UrlShortenerService::new()
+        .with_snapshot_interval(snapshot_interval)
+        .with_lookup_leniency(leniency);
+    for i in 0..links - 1 {
+        let slug = Slug::from(format!("link{i}"));
+        service.handle_create_short_link(Url::from("https://example.com"), Some(slug)).unwrap();
+    }
+    service.handle_create_short_link(Url::from("https://example.com/target"), Some(slug.clone())).unwrap();
+
+    service
+}
+
+fn redirects(c: &mut Criterion) {
+    let short = Slug::from("promo");
+    let long = Slug::from("a-rather-long-campaign-slug-with-a-date-2026-10-16");
+
+    for (label, slug) in [("short", &short), ("long", &long)] {
+        let mut hot = service(slug, 10_000, 100);
+        c.bench_function(&format!("redirect {label}"), |b| {
+            b.iter(|| hot.handle_redirect(black_box(slug.clone())).unwrap())
+        });
+
+        let folded = Slug::from(slug.0.to_uppercase());
+        c.bench_function(&format!("redirect {label}, case-folded"), |b| {
+            b.iter(|| hot.handle_redirect(black_box(folded.clone())).unwrap())
+        });
+
+        let lookup = service(slug, 10_000, 100);
+        c.bench_function(&format!("resolve_url_borrowed {label}"), |b| {
+            b.iter(|| lookup.resolve_url_borrowed(black_box(slug)).map(str::len).unwrap())
+        });
+    }
+
+    // Without snapshots every redirect replays the link's whole history, so
+    // each batch starts over from a short one.
+    c.bench_function("redirect short, no snapshots", |b| {
+        b.iter_batched_ref(
+            || service(&short, 100, 0),
+            |service| service.handle_redirect(black_box(short.clone())).unwrap(),
+            BatchSize::SmallInput
+        )
+    });
+}
+
+criterion_group!(benches, redirects);
+criterion_main!(benches);
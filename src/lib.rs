@@ -0,0 +1,8575 @@
+//! ## Task Description
+//!
+//! The goal is to develop a backend service for shortening URLs using CQRS
+//! (Command Query Responsibility Segregation) and ES (Event Sourcing)
+//! approaches. The service should support the following features:
+//!
+//! ## Functional Requirements
+//!
+//! ### Creating a short link with a random slug
+//!
+//! The user sends a long URL, and the service returns a shortened URL with a
+//! random slug.
+//!
+//! ### Creating a short link with a predefined slug
+//!
+//! The user sends a long URL along with a predefined slug, and the service
+//! checks if the slug is unique. If it is unique, the service creates the short
+//! link.
+//!
+//! ### Counting the number of redirects for the link
+//!
+//! - Every time a user accesses the short link, the click count should
+//!   increment.
+//! - The click count can be retrieved via an API.
+//!
+//! ### CQRS+ES Architecture
+//!
+//! CQRS: Commands (creating links, updating click count) are separated from
+//! queries (retrieving link information).
+//!
+//! Event Sourcing: All state changes (link creation, click count update) must be
+//! recorded as events, which can be replayed to reconstruct the system's state.
+//!
+//! ### Technical Requirements
+//!
+//! - The service must be built using CQRS and Event Sourcing approaches.
+//! - The service must be possible to run in Rust Playground (so no database like
+//!   Postgres is allowed)
+//! - Public API already written for this task must not be changed (any change to
+//!   the public API items must be considered as breaking change).
+
+#![allow(unused_variables, dead_code)]
+
+/// All possible errors of the [`UrlShortenerService`].
+///
+/// With the `serde` feature, errors serialize as their
+/// [`ShortenerError::code`].
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ShortenerError {
+    /// This error occurs when an invalid [`Url`] is provided for shortening.
+    #[cfg_attr(feature = "serde", serde(rename = "URL_INVALID"))]
+    InvalidUrl,
+
+    /// This error occurs when an attempt is made to use a slug (custom alias)
+    /// that already exists.
+    #[cfg_attr(feature = "serde", serde(rename = "SLUG_IN_USE"))]
+    SlugAlreadyInUse,
+
+    /// This error occurs when a custom slug is empty, longer than 64
+    /// characters, or contains characters other than ASCII letters, digits,
+    /// `_` and `-`.
+    #[cfg_attr(feature = "serde", serde(rename = "SLUG_INVALID"))]
+    InvalidSlug,
+
+    /// This error occurs when the provided [`Slug`] does not map to any existing
+    /// short link.
+    #[cfg_attr(feature = "serde", serde(rename = "SLUG_NOT_FOUND"))]
+    SlugNotFound,
+
+    /// This error occurs when [`ServingHints`] contain a malformed or
+    /// oversized header.
+    #[cfg_attr(feature = "serde", serde(rename = "SERVING_HINTS_INVALID"))]
+    InvalidServingHints,
+
+    /// This error occurs when redirecting to a link marked dead while its
+    /// [`DeadLinkPolicy`] is [`DeadLinkPolicy::ReturnError`].
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_DEAD"))]
+    LinkDead,
+
+    /// This error occurs when trying to change a link that was made
+    /// permanent.
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_PERMANENT"))]
+    LinkPermanent,
+
+    /// This error occurs when every generated slug was already taken.
+    #[cfg_attr(feature = "serde", serde(rename = "SLUG_GENERATION_FAILED"))]
+    SlugGenerationFailed,
+
+    /// This error occurs when redirecting to a link past its expiry.
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_EXPIRED"))]
+    LinkExpired,
+
+    /// This error occurs when an imported event log is malformed or records
+    /// an impossible sequence of events.
+    #[cfg_attr(feature = "serde", serde(rename = "EVENT_LOG_INVALID"))]
+    InvalidEventLog,
+
+    /// This error occurs when redirecting to a disabled link.
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_DISABLED"))]
+    LinkDisabled,
+
+    /// This error occurs when redirecting to a link that already served as
+    /// many redirects as its limit allows.
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_EXHAUSTED"))]
+    LinkExhausted,
+
+    /// This error occurs when deleting a link its bootstrap seed made
+    /// undeletable, or creating a link under a slug reserved for a seed.
+    #[cfg_attr(feature = "serde", serde(rename = "LINK_PROTECTED"))]
+    ProtectedLink,
+
+    /// This error occurs when reading stats with a token that was never
+    /// issued, has expired or was revoked. The cases are not told apart.
+    #[cfg_attr(feature = "serde", serde(rename = "STATS_TOKEN_INVALID"))]
+    InvalidStatsToken,
+}
+
+impl ShortenerError {
+    /// Returns a stable machine-readable code of the error.
+    ///
+    /// Codes never change once published, even if the variants are renamed,
+    /// so they are safe to expose to clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ShortenerError::InvalidUrl => "URL_INVALID",
+            ShortenerError::SlugAlreadyInUse => "SLUG_IN_USE",
+            ShortenerError::InvalidSlug => "SLUG_INVALID",
+            ShortenerError::SlugNotFound => "SLUG_NOT_FOUND",
+            ShortenerError::InvalidServingHints => "SERVING_HINTS_INVALID",
+            ShortenerError::LinkDead => "LINK_DEAD",
+            ShortenerError::LinkPermanent => "LINK_PERMANENT",
+            ShortenerError::SlugGenerationFailed => "SLUG_GENERATION_FAILED",
+            ShortenerError::LinkExpired => "LINK_EXPIRED",
+            ShortenerError::InvalidEventLog => "EVENT_LOG_INVALID",
+            ShortenerError::LinkDisabled => "LINK_DISABLED",
+            ShortenerError::LinkExhausted => "LINK_EXHAUSTED",
+            ShortenerError::ProtectedLink => "LINK_PROTECTED",
+            ShortenerError::InvalidStatsToken => "STATS_TOKEN_INVALID",
+        }
+    }
+}
+
+/// Messages are short lowercase sentences fit to show to users, followed by
+/// the [`ShortenerError::code`] in parentheses, e.g. "the slug is already in
+/// use (SLUG_IN_USE)". They don't name the offending slug or URL, as the
+/// variants carry no data; callers know which input they passed.
+impl std::fmt::Display for ShortenerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            ShortenerError::InvalidUrl => "the URL is invalid",
+            ShortenerError::SlugAlreadyInUse => "the slug is already in use",
+            ShortenerError::InvalidSlug => "the slug is malformed",
+            ShortenerError::SlugNotFound => "the requested slug was not found",
+            ShortenerError::InvalidServingHints => "the serving hints are malformed or too large",
+            ShortenerError::LinkDead => "the link's destination is dead",
+            ShortenerError::LinkPermanent => "the link is permanent and cannot be changed",
+            ShortenerError::SlugGenerationFailed => "no free slug could be generated",
+            ShortenerError::LinkExpired => "the link has expired",
+            ShortenerError::InvalidEventLog => "the event log is malformed",
+            ShortenerError::LinkDisabled => "the link is disabled",
+            ShortenerError::LinkExhausted => "the link has reached its redirect limit",
+            ShortenerError::ProtectedLink => "the link is protected by its bootstrap seed",
+            ShortenerError::InvalidStatsToken => "the stats token is unknown, expired or revoked",
+        };
+
+        write!(f, "{message} ({})", self.code())
+    }
+}
+
+impl std::error::Error for ShortenerError {}
+
+/// A unique string (or alias) that represents the shortened version of the
+/// URL.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Slug(pub String);
+
+/// Compact id of a [`Slug`], handed out by the service the first time the
+/// slug gets an event and never reused, see [`UrlShortenerService::slug_id`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct SlugId(pub u64);
+
+/// The original URL that the short link points to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Url(pub String);
+
+/// Reason why a [`Url`] fails validation. With the `serde` feature, reasons
+/// serialize as their [`InvalidUrlReason::code`].
+#[derive(Clone, Copy, Debug, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InvalidUrlReason {
+    /// The URL is empty.
+    #[cfg_attr(feature = "serde", serde(rename = "URL_EMPTY"))]
+    Empty,
+
+    /// The URL doesn't use an allowed scheme.
+    #[cfg_attr(feature = "serde", serde(rename = "URL_INVALID_SCHEME"))]
+    InvalidScheme,
+
+    /// The URL has no valid host.
+    #[cfg_attr(feature = "serde", serde(rename = "URL_INVALID_HOST"))]
+    InvalidHost,
+
+    /// The URL is well-formed but not allowed by policy.
+    #[cfg_attr(feature = "serde", serde(rename = "URL_BLOCKED"))]
+    Blocked,
+}
+
+impl InvalidUrlReason {
+    /// Returns a stable machine-readable code of the reason, see
+    /// [`ShortenerError::code`].
+    pub fn code(&self) -> &'static str {
+        match self {
+            InvalidUrlReason::Empty => "URL_EMPTY",
+            InvalidUrlReason::InvalidScheme => "URL_INVALID_SCHEME",
+            InvalidUrlReason::InvalidHost => "URL_INVALID_HOST",
+            InvalidUrlReason::Blocked => "URL_BLOCKED",
+        }
+    }
+}
+
+/// Rules a destination [`Url`] must satisfy.
+pub trait UrlValidator {
+    /// Checks the URL, returning the first rule it breaks.
+    fn validate(&self, url: &Url) -> Result<(), InvalidUrlReason>;
+
+    /// Returns the form in which a valid URL is stored.
+    fn normalize(&self, url: &Url) -> Url {
+        url.clone()
+    }
+}
+
+/// The [`UrlValidator`] used by the service when creating links.
+#[cfg(not(feature = "strict-url"))]
+pub type ServiceUrlValidator = DefaultUrlValidator;
+
+/// The [`UrlValidator`] used by the service when creating links.
+#[cfg(feature = "strict-url")]
+pub type ServiceUrlValidator = StrictUrlValidator;
+
+/// A dependency-free heuristic [`UrlValidator`], used by the service unless
+/// the `strict-url` feature is enabled.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultUrlValidator;
+
+impl UrlValidator for DefaultUrlValidator {
+    /// This is simple implementation to avoid external dependencies.
+    /// In production use "url" package instead
+    fn validate(&self, url: &Url) -> Result<(), InvalidUrlReason> {
+        if url.0.is_empty() {
+            return Err(InvalidUrlReason::Empty);
+        }
+
+        let scheme = url.0.split_once("://").map(|(scheme, _)| scheme.to_ascii_lowercase());
+        if !matches!(scheme.as_deref(), Some("http" | "https")) {
+            return Err(InvalidUrlReason::InvalidScheme);
+        }
+
+        if !url.0.contains('.') {
+            return Err(InvalidUrlReason::InvalidHost);
+        }
+
+        Ok(())
+    }
+
+    /// Lowercases the scheme and host, drops a default port and a slash
+    /// standing for the root path, so that e.g. `HTTPS://Example.com:443/`
+    /// is stored as `https://example.com`.
+    fn normalize(&self, url: &Url) -> Url {
+        let Some((scheme, rest)) = url.0.split_once("://") else {
+            return url.clone();
+        };
+        let scheme = scheme.to_ascii_lowercase();
+
+        let (authority, mut tail) = rest.split_at(rest.find(['/', '?', '#']).unwrap_or(rest.len()));
+        let (userinfo, host) = match authority.rsplit_once('@') {
+            Some((userinfo, host)) => (Some(userinfo), host),
+            None => (None, authority)
+        };
+
+        let mut host = host.to_ascii_lowercase();
+        let default_port = if scheme == "https" { ":443" } else { ":80" };
+        if let Some(stripped) = host.strip_suffix(default_port) {
+            host.truncate(stripped.len());
+        }
+
+        if let Some(after_root) = tail.strip_prefix('/') {
+            if after_root.is_empty() || after_root.starts_with(['?', '#']) {
+                tail = after_root;
+            }
+        }
+
+        let userinfo = userinfo.map(|userinfo| format!("{userinfo}@")).unwrap_or_default();
+        Url(format!("{scheme}://{userinfo}{host}{tail}"))
+    }
+}
+
+/// A [`UrlValidator`] that fully parses URLs with the `url` crate. Requires
+/// an absolute `http` or `https` URL with a host, and stores it normalized,
+/// e.g. with a lowercase host and without a default port.
+#[cfg(feature = "strict-url")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StrictUrlValidator;
+
+#[cfg(feature = "strict-url")]
+impl UrlValidator for StrictUrlValidator {
+    fn validate(&self, url: &Url) -> Result<(), InvalidUrlReason> {
+        if url.0.is_empty() {
+            return Err(InvalidUrlReason::Empty);
+        }
+
+        let parsed = match url::Url::parse(&url.0) {
+            Ok(parsed) => parsed,
+            Err(url::ParseError::RelativeUrlWithoutBase) => return Err(InvalidUrlReason::InvalidScheme),
+            Err(_) => return Err(InvalidUrlReason::InvalidHost)
+        };
+
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err(InvalidUrlReason::InvalidScheme);
+        }
+
+        // Domains with empty labels, such as `.` or `foo.`, are rejected.
+        match parsed.host() {
+            Some(url::Host::Domain(domain)) if domain.split('.').any(str::is_empty) => {
+                Err(InvalidUrlReason::InvalidHost)
+            }
+            Some(_) => Ok(()),
+            None => Err(InvalidUrlReason::InvalidHost)
+        }
+    }
+
+    /// Stores the URL as serialized by the `url` crate, except that a slash
+    /// standing for the root path is dropped, as [`DefaultUrlValidator`]
+    /// does.
+    fn normalize(&self, url: &Url) -> Url {
+        match url::Url::parse(&url.0) {
+            Ok(parsed) => {
+                let mut normalized = parsed.to_string();
+                if parsed.path() == "/" {
+                    normalized.remove(parsed[..url::Position::AfterPort].len());
+                }
+                Url(normalized)
+            }
+            Err(_) => url.clone()
+        }
+    }
+}
+
+/// Source of slugs for links created without one.
+pub trait SlugGenerator {
+    /// Returns a candidate slug. The service retries if it is taken. `now`
+    /// is the current time according to the service's [`Clock`].
+    fn generate(&mut self, now: SystemTime) -> Slug;
+}
+
+/// The [`SlugGenerator`] used by the service unless another one is set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultSlugGenerator;
+
+impl SlugGenerator for DefaultSlugGenerator {
+    /// Use external crates to generate better slug
+    fn generate(&mut self, now: SystemTime) -> Slug {
+        let nanos = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            .to_string();
+
+        let mut str = "rand".to_string();
+        str.push_str(&nanos);
+
+        Slug(str)
+    }
+}
+
+const BASE62_ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// A [`SlugGenerator`] producing random slugs from `[0-9A-Za-z]`, which are
+/// shorter than the default ones and don't reveal when they were made.
+/// A length of 0 or above 64 yields slugs the service rejects with
+/// [`ShortenerError::InvalidSlug`].
+#[derive(Clone, Debug)]
+pub struct Base62Generator {
+    len: usize,
+    state: u64
+}
+
+impl Base62Generator {
+    /// Creates a generator of `len`-character slugs, randomly seeded.
+    pub fn new(len: usize) -> Self {
+        Self {
+            len,
+            state: RandomState::new().build_hasher().finish()
+        }
+    }
+
+    /// Seeds the generator; generators with the same seed and length
+    /// produce the same slugs.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.state = seed;
+        self
+    }
+
+    /// Advances the SplitMix64 sequence.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Default for Base62Generator {
+    /// Generates 7-character slugs.
+    fn default() -> Self {
+        Self::new(7)
+    }
+}
+
+impl SlugGenerator for Base62Generator {
+    fn generate(&mut self, _now: SystemTime) -> Slug {
+        let slug = (0..self.len)
+            .map(|_| BASE62_ALPHABET[(self.next_u64() % 62) as usize] as char)
+            .collect();
+
+        Slug(slug)
+    }
+}
+
+/// Words that generated slugs must not spell, see
+/// [`UrlShortenerService::with_word_filter`].
+pub trait WordList {
+    /// Returns whether `word`, in lowercase, is on the list.
+    fn contains(&self, word: &str) -> bool;
+}
+
+/// A small embedded list of English and German words that would be
+/// embarrassing or misleading in a short link.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DefaultWordList;
+
+/// The words of [`DefaultWordList`], in lowercase.
+const DEFAULT_WORDS: &[&str] = &[
+    "anal", "arsch", "ass", "bitch", "cock", "crap", "cunt", "damn", "dick", "dildo", "drug", "fick", "fotze",
+    "fuck", "hitler", "hure", "kill", "kotz", "nazi", "nigger", "nutte", "penis", "piss", "porn", "pussy", "rape",
+    "scheiss", "scheisse", "schlampe", "sex", "shit", "slut", "tits", "titte", "wank", "whore", "wichs",
+];
+
+impl WordList for DefaultWordList {
+    fn contains(&self, word: &str) -> bool {
+        DEFAULT_WORDS.contains(&word)
+    }
+}
+
+impl WordList for HashSet<String> {
+    fn contains(&self, word: &str) -> bool {
+        HashSet::contains(self, word)
+    }
+}
+
+impl WordList for &'static [&'static str] {
+    fn contains(&self, word: &str) -> bool {
+        <[&str]>::contains(self, &word)
+    }
+}
+
+/// Rejects generated slugs spelling a listed word, see
+/// [`UrlShortenerService::with_word_filter`].
+struct WordFilter {
+    list: Box<dyn WordList>,
+    min_len: usize
+}
+
+impl WordFilter {
+    /// Returns whether the slug, or any part of it at least `min_len`
+    /// characters long, is a listed word, ignoring case.
+    fn rejects(&self, slug: &Slug) -> bool {
+        let chars: Vec<char> = slug.0.to_lowercase().chars().collect();
+        let listed = |start: usize, end: usize| self.list.contains(&chars[start..end].iter().collect::<String>());
+        let min_len = self.min_len.max(1);
+
+        listed(0, chars.len())
+            || (0..chars.len()).any(|start| (start + min_len..=chars.len()).any(|end| listed(start, end)))
+    }
+}
+
+/// Source of the current time, replaceable so tests can control it.
+///
+/// Times are [`SystemTime`]s rather than [`Duration`]s since the epoch, so a
+/// point in time can't be mistaken for a length of time such as a TTL;
+/// `now.duration_since(SystemTime::UNIX_EPOCH)` gives the latter.
+pub trait Clock {
+    /// Returns the current time.
+    fn now(&self) -> SystemTime;
+}
+
+impl<C: Clock + ?Sized> Clock for Box<C> {
+    fn now(&self) -> SystemTime {
+        (**self).now()
+    }
+}
+
+/// The [`Clock`] used by the service unless another one is set.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A [`Clock`] that is stopped at the given time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// Shortened URL representation.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ShortLink {
+    /// A unique string (or alias) that represents the shortened version of the
+    /// URL.
+    pub slug: Slug,
+
+    /// The original URL that the short link points to.
+    pub url: Url,
+}
+
+/// Statistics of the [`ShortLink`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    /// [`ShortLink`] to which this [`Stats`] are related.
+    pub link: ShortLink,
+
+    /// Count of redirects of the [`ShortLink`], saturating at `u64::MAX`.
+    pub redirects: u64,
+
+    /// Whether the [`ShortLink`] currently redirects.
+    pub enabled: bool,
+
+    /// When the [`ShortLink`] was created, according to the service's
+    /// [`Clock`]. Migrated links carry [`MigrationOptions::created_at`], or
+    /// the time of their migration if it is unset. A [`SystemTime`] like
+    /// every time in this crate, see [`Clock`]; for seconds since the epoch,
+    /// use `created_at.duration_since(SystemTime::UNIX_EPOCH)`.
+    pub created_at: SystemTime,
+
+    /// When the [`ShortLink`] last redirected, or `None` if it never did.
+    /// Migrated redirects carry no time and leave this unset. A
+    /// [`SystemTime`] like [`Stats::created_at`], not a [`Duration`] since
+    /// the epoch.
+    pub last_redirected_at: Option<SystemTime>,
+}
+
+/// HTTP status a serving layer should use for a redirect.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RedirectKind {
+    /// `301 Moved Permanently`.
+    MovedPermanently,
+
+    /// `302 Found`.
+    #[default]
+    Found,
+
+    /// `307 Temporary Redirect`.
+    TemporaryRedirect,
+
+    /// `308 Permanent Redirect`.
+    PermanentRedirect,
+}
+
+impl RedirectKind {
+    /// Returns the HTTP status code of the redirect.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            RedirectKind::MovedPermanently => 301,
+            RedirectKind::Found => 302,
+            RedirectKind::TemporaryRedirect => 307,
+            RedirectKind::PermanentRedirect => 308,
+        }
+    }
+}
+
+/// Per-link hints telling a serving layer how to answer a redirect.
+#[derive(Clone, Debug, Default, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ServingHints {
+    /// Redirect status to respond with.
+    pub status: RedirectKind,
+
+    /// Value of the `Cache-Control` header, if any.
+    pub cache_control: Option<String>,
+
+    /// Additional response headers as `(name, value)` pairs.
+    pub extra_headers: Vec<(String, String)>,
+}
+
+/// How redirects to a link whose destination was found dead are served.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeadLinkPolicy {
+    /// Redirect as usual.
+    #[default]
+    ServeAnyway,
+
+    /// Redirect after showing a warning page.
+    ServeWithInterstitial,
+
+    /// Refuse the redirect with [`ShortenerError::LinkDead`].
+    ReturnError,
+}
+
+/// Commands for CQRS.
+pub mod commands {
+    use std::time::{Duration, SystemTime};
+    use super::{DeadLinkPolicy, ServingHints, ShortLink, ShortenerError, Slug, StatsScope, StatsToken, Url};
+
+    /// Result of a configuration command that succeeded.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum CommandOutcome {
+        /// The change was recorded as an event.
+        Applied,
+
+        /// The requested configuration was already in effect, so no event
+        /// was recorded.
+        Unchanged,
+    }
+
+    /// Trait for command handlers.
+    pub trait CommandHandler {
+        /// Creates a new short link. It accepts the original url and an
+        /// optional [`Slug`]. If a [`Slug`] is not provided, the service will generate
+        /// one. Returns the newly created [`ShortLink`].
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::{Base62Generator, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new().with_slug_generator(Base62Generator::new(7).with_seed(42));
+        ///
+        /// let link = service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        /// assert_eq!(link.slug, Slug::from("docs"));
+        /// let generated = service.handle_create_short_link(Url::from("https://example.com"), None)?;
+        /// assert_eq!(generated.slug.0.len(), 7);
+        ///
+        /// let taken = service.handle_create_short_link(Url::from("https://example.org"), Some(Slug::from("docs")));
+        /// assert_eq!(taken, Err(ShortenerError::SlugAlreadyInUse));
+        /// assert_eq!(service.handle_create_short_link(Url::from("not a url"), None), Err(ShortenerError::InvalidUrl));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_create_short_link(
+            &mut self,
+            url: Url,
+            slug: Option<Slug>,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Processes a redirection by [`Slug`], returning the associated
+        /// [`ShortLink`] or a [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        ///
+        /// assert_eq!(service.handle_redirect(Slug::from("docs"))?.url, Url::from("https://example.com"));
+        /// service.handle_redirect(Slug::from("docs"))?;
+        /// assert_eq!(service.get_stats(Slug::from("docs"))?.redirects, 2);
+        ///
+        /// assert_eq!(service.handle_redirect(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_redirect(
+            &mut self,
+            slug: Slug,
+        ) -> Result<ShortLink, ShortenerError>;
+    }
+
+    /// Commands beyond [`CommandHandler`], kept in their own trait so that
+    /// implementing the original contract doesn't require them.
+    pub trait ExtendedCommandHandler: CommandHandler {
+        /// Creates a new short link like
+        /// [`CommandHandler::handle_create_short_link`] that stops redirecting
+        /// at `expires_at`. Its [`Stats`] stay available afterwards.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+        /// let url = Url::from("https://example.com");
+        /// let tomorrow = now + Duration::from_secs(86_400);
+        ///
+        /// service.handle_create_short_link_with_expiry(url.clone(), Some(Slug::from("sale")), tomorrow)?;
+        /// service.handle_create_short_link_with_expiry(url, Some(Slug::from("over")), now)?;
+        ///
+        /// assert!(service.handle_redirect(Slug::from("sale")).is_ok());
+        /// assert_eq!(service.handle_redirect(Slug::from("over")), Err(ShortenerError::LinkExpired));
+        /// assert_eq!(service.get_stats(Slug::from("over"))?.redirects, 0);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`Stats`]: super::Stats
+        fn handle_create_short_link_with_expiry(
+            &mut self,
+            url: Url,
+            slug: Option<Slug>,
+            expires_at: SystemTime,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Creates a new short link that expires `ttl_secs` seconds from now,
+        /// as told by the service's clock. See
+        /// [`ExtendedCommandHandler::handle_create_short_link_with_expiry`].
+        /// A TTL reaching beyond representable time never elapses, so the
+        /// link is created without expiry.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::ExtendedCommandHandler;
+        /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+        ///
+        /// let url = Url::from("https://example.com");
+        /// let link = service.handle_create_with_ttl(url, Some(Slug::from("hour")), 3_600)?;
+        /// assert_eq!(service.expires_at(&link.slug), Some(now + Duration::from_secs(3_600)));
+        ///
+        /// assert_eq!(service.handle_create_with_ttl(Url::from(""), None, 3_600), Err(ShortenerError::InvalidUrl));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_create_with_ttl(
+            &mut self,
+            url: Url,
+            slug: Option<Slug>,
+            ttl_secs: u64,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Creates a new short link that redirects only once. Later redirects
+        /// fail with [`ShortenerError::SlugNotFound`], while its [`Stats`]
+        /// stay available.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_one_time(Url::from("https://example.com/invite"), Some(Slug::from("invite")))?;
+        ///
+        /// assert!(service.handle_redirect(Slug::from("invite")).is_ok());
+        /// assert_eq!(service.handle_redirect(Slug::from("invite")), Err(ShortenerError::SlugNotFound));
+        /// assert_eq!(service.get_stats(Slug::from("invite"))?.redirects, 1);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`Stats`]: super::Stats
+        fn handle_create_one_time(
+            &mut self,
+            url: Url,
+            slug: Option<Slug>,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Creates a new short link that serves at most `max_redirects`
+        /// redirects. Later redirects fail with
+        /// [`ShortenerError::LinkExhausted`], while its [`Stats`] stay
+        /// available.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_with_limit(Url::from("https://example.com"), Some(Slug::from("twice")), 2)?;
+        ///
+        /// service.handle_redirect(Slug::from("twice"))?;
+        /// service.handle_redirect(Slug::from("twice"))?;
+        /// assert_eq!(service.handle_redirect(Slug::from("twice")), Err(ShortenerError::LinkExhausted));
+        /// assert_eq!(service.get_stats(Slug::from("twice"))?.redirects, 2);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`Stats`]: super::Stats
+        fn handle_create_with_limit(
+            &mut self,
+            url: Url,
+            slug: Option<Slug>,
+            max_redirects: u64,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Returns an existing link to `url`, compared in normalized form, or
+        /// creates one with a generated [`Slug`] if none does. Only links that
+        /// would serve a redirect count as existing, so disabled, expired,
+        /// consumed, exhausted and refused dead links don't; among several
+        /// matches the lowest slug wins.
+        ///
+        /// ## Errors
+        ///
+        /// See [`ShortenerError`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::ExtendedCommandHandler;
+        /// use url_shortener::{ShortenerError, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        ///
+        /// let link = service.handle_get_or_create(Url::from("https://example.com"))?;
+        /// assert_eq!(service.handle_get_or_create(Url::from("HTTPS://Example.com/"))?, link);
+        ///
+        /// assert_eq!(service.handle_get_or_create(Url::from("ftp://example.com")), Err(ShortenerError::InvalidUrl));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_get_or_create(
+            &mut self,
+            url: Url,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Sets the [`ServingHints`] of a short link, or clears them when
+        /// `None` is given. Returns [`CommandOutcome::Unchanged`] without
+        /// recording an event if the hints are already in effect.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist,
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent and
+        /// [`ShortenerError::InvalidServingHints`] if a header is malformed or
+        /// too large.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, CommandOutcome, ExtendedCommandHandler};
+        /// use url_shortener::{RedirectKind, ServingHints, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        /// let hints = ServingHints { status: RedirectKind::MovedPermanently, ..ServingHints::default() };
+        ///
+        /// let set = service.handle_set_serving_hints(Slug::from("docs"), Some(hints.clone()))?;
+        /// assert_eq!(set, CommandOutcome::Applied);
+        /// assert_eq!(service.handle_set_serving_hints(Slug::from("docs"), Some(hints))?, CommandOutcome::Unchanged);
+        /// assert_eq!(service.serving_hints(&Slug::from("docs"))?.status, RedirectKind::MovedPermanently);
+        ///
+        /// let headers = vec![("Location".to_string(), "https://evil.com".to_string())];
+        /// let location = ServingHints { extra_headers: headers, ..ServingHints::default() };
+        /// let rejected = service.handle_set_serving_hints(Slug::from("docs"), Some(location));
+        /// assert_eq!(rejected, Err(ShortenerError::InvalidServingHints));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_set_serving_hints(
+            &mut self,
+            slug: Slug,
+            hints: Option<ServingHints>,
+        ) -> Result<CommandOutcome, ShortenerError>;
+
+        /// Marks a short link's destination as dead, e.g. after an external
+        /// checker found it returning `404`. `evidence` describes the finding.
+        /// Marking an already dead link replaces the evidence.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist and
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com/gone"), Some(Slug::from("old")))?;
+        ///
+        /// service.handle_mark_dead(Slug::from("old"), "404".to_string())?;
+        /// assert_eq!(service.dead_links(), [(Slug::from("old"), "404".to_string())]);
+        ///
+        /// let missing = service.handle_mark_dead(Slug::from("missing"), "404".to_string());
+        /// assert_eq!(missing, Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_mark_dead(
+            &mut self,
+            slug: Slug,
+            evidence: String,
+        ) -> Result<(), ShortenerError>;
+
+        /// Marks a short link's destination as alive again.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist and
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com/flaky"), Some(Slug::from("flaky")))?;
+        /// service.handle_mark_dead(Slug::from("flaky"), "503".to_string())?;
+        ///
+        /// service.handle_mark_alive(Slug::from("flaky"))?;
+        /// assert!(service.dead_links().is_empty());
+        ///
+        /// assert_eq!(service.handle_mark_alive(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_mark_alive(
+            &mut self,
+            slug: Slug,
+        ) -> Result<(), ShortenerError>;
+
+        /// Overrides the service's [`DeadLinkPolicy`] for one short link, or
+        /// removes the override when `None` is given. Returns
+        /// [`CommandOutcome::Unchanged`] without recording an event if the
+        /// override is already in effect.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist and
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, CommandOutcome, ExtendedCommandHandler};
+        /// use url_shortener::{DeadLinkPolicy, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com/gone"), Some(Slug::from("old")))?;
+        /// service.handle_mark_dead(Slug::from("old"), "404".to_string())?;
+        /// assert!(service.handle_redirect(Slug::from("old")).is_ok());
+        ///
+        /// let policy = Some(DeadLinkPolicy::ReturnError);
+        /// assert_eq!(service.handle_set_dead_link_policy(Slug::from("old"), policy)?, CommandOutcome::Applied);
+        /// assert_eq!(service.handle_redirect(Slug::from("old")), Err(ShortenerError::LinkDead));
+        ///
+        /// let missing = service.handle_set_dead_link_policy(Slug::from("missing"), None);
+        /// assert_eq!(missing, Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_set_dead_link_policy(
+            &mut self,
+            slug: Slug,
+            policy: Option<DeadLinkPolicy>,
+        ) -> Result<CommandOutcome, ShortenerError>;
+
+        /// Freezes a short link, e.g. because it was printed. Every later
+        /// command that would change the link fails with
+        /// [`ShortenerError::LinkPermanent`]; redirects keep working. Returns
+        /// [`CommandOutcome::Unchanged`] if the link is already permanent.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, CommandOutcome, ExtendedCommandHandler};
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("flyer")))?;
+        ///
+        /// assert_eq!(service.handle_make_permanent(Slug::from("flyer"))?, CommandOutcome::Applied);
+        /// assert!(service.is_permanent(&Slug::from("flyer")));
+        /// assert!(service.handle_redirect(Slug::from("flyer")).is_ok());
+        ///
+        /// let moved = service.handle_update_target(Slug::from("flyer"), Url::from("https://example.org"));
+        /// assert_eq!(moved, Err(ShortenerError::LinkPermanent));
+        /// assert_eq!(service.handle_make_permanent(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_make_permanent(
+            &mut self,
+            slug: Slug,
+        ) -> Result<CommandOutcome, ShortenerError>;
+
+        /// Deletes a short link. The slug stops resolving and may be used for
+        /// a new link afterwards, unless it is reserved for a seed, see
+        /// [`SeedFlags::reserved`](super::SeedFlags::reserved).
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist,
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent and
+        /// [`ShortenerError::ProtectedLink`] if its seed made it undeletable.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        ///
+        /// service.handle_delete_short_link(Slug::from("docs"))?;
+        /// assert_eq!(service.handle_redirect(Slug::from("docs")), Err(ShortenerError::SlugNotFound));
+        /// let reused = service.handle_create_short_link(Url::from("https://example.org"), Some(Slug::from("docs")));
+        /// assert!(reused.is_ok());
+        ///
+        /// assert_eq!(service.handle_delete_short_link(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_delete_short_link(
+            &mut self,
+            slug: Slug,
+        ) -> Result<(), ShortenerError>;
+
+        /// Points an existing short link to a new URL, keeping its redirect
+        /// count. A dead-link mark is cleared, since it concerned the old
+        /// destination. Returns the updated [`ShortLink`].
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist,
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent and
+        /// [`ShortenerError::InvalidUrl`] if the new URL is invalid.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        /// service.handle_redirect(Slug::from("docs"))?;
+        ///
+        /// let link = service.handle_update_target(Slug::from("docs"), Url::from("https://example.org"))?;
+        /// assert_eq!(link.url, Url::from("https://example.org"));
+        /// assert_eq!(service.get_stats(Slug::from("docs"))?.redirects, 1);
+        ///
+        /// let invalid = service.handle_update_target(Slug::from("docs"), Url::from(""));
+        /// assert_eq!(invalid, Err(ShortenerError::InvalidUrl));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_update_target(
+            &mut self,
+            slug: Slug,
+            new_url: Url,
+        ) -> Result<ShortLink, ShortenerError>;
+
+        /// Pauses or resumes a short link. While disabled, redirects fail
+        /// with [`ShortenerError::LinkDisabled`] and aren't counted. Returns
+        /// [`CommandOutcome::Unchanged`] without recording an event if the
+        /// link is already in the requested state.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist and
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, CommandOutcome, ExtendedCommandHandler};
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("promo")))?;
+        ///
+        /// assert_eq!(service.handle_set_link_enabled(Slug::from("promo"), false)?, CommandOutcome::Applied);
+        /// assert_eq!(service.handle_set_link_enabled(Slug::from("promo"), false)?, CommandOutcome::Unchanged);
+        /// assert_eq!(service.handle_redirect(Slug::from("promo")), Err(ShortenerError::LinkDisabled));
+        /// assert!(!service.get_stats(Slug::from("promo"))?.enabled);
+        ///
+        /// assert_eq!(service.handle_set_link_enabled(Slug::from("missing"), true), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_set_link_enabled(
+            &mut self,
+            slug: Slug,
+            enabled: bool,
+        ) -> Result<CommandOutcome, ShortenerError>;
+
+        /// Issues a token that lets its holder read the stats of a link
+        /// with [`ExtendedQueryHandler::get_stats_with_token`] until
+        /// `expires_at`, according to the service's clock, or until it is
+        /// revoked. `scope` decides what is shown besides the stats.
+        ///
+        /// The event records only a hash of the token, so neither the log
+        /// nor its exports reveal it. The hash is a SipHash, which keeps the
+        /// token out of storage but is not a cryptographic hash.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, StatsScope, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("promo")))?;
+        /// service.handle_redirect(Slug::from("promo"))?;
+        ///
+        /// let expires_at = SystemTime::now() + Duration::from_secs(3_600);
+        /// let token = service.handle_issue_stats_token(Slug::from("promo"), expires_at, StatsScope::default())?;
+        /// assert_eq!(service.get_stats_with_token(token.as_str())?.stats.redirects, 1);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`ExtendedQueryHandler::get_stats_with_token`]: super::queries::ExtendedQueryHandler::get_stats_with_token
+        fn handle_issue_stats_token(
+            &mut self,
+            slug: Slug,
+            expires_at: SystemTime,
+            scope: StatsScope,
+        ) -> Result<StatsToken, ShortenerError>;
+
+        /// Revokes a token issued by
+        /// [`ExtendedCommandHandler::handle_issue_stats_token`] before it
+        /// expires.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::InvalidStatsToken`] if the token is unknown or
+        /// already revoked.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, StatsScope, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("promo")))?;
+        /// let expires_at = SystemTime::now() + Duration::from_secs(3_600);
+        /// let token = service.handle_issue_stats_token(Slug::from("promo"), expires_at, StatsScope::default())?;
+        ///
+        /// service.handle_revoke_stats_token(token.as_str())?;
+        /// assert_eq!(service.get_stats_with_token(token.as_str()), Err(ShortenerError::InvalidStatsToken));
+        /// assert_eq!(service.handle_revoke_stats_token(token.as_str()), Err(ShortenerError::InvalidStatsToken));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_revoke_stats_token(&mut self, token: &str) -> Result<(), ShortenerError>;
+
+        /// Sets when an existing link expires or, with `None`, makes it
+        /// never expire. Extending the expiry of an expired link revives it.
+        /// Returns [`CommandOutcome::Unchanged`] without recording an event
+        /// if the expiry is already the requested one.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist and
+        /// [`ShortenerError::LinkPermanent`] if the link is permanent.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, CommandOutcome, ExtendedCommandHandler};
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("sale")))?;
+        ///
+        /// let deadline = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+        /// assert_eq!(service.handle_set_expiry(Slug::from("sale"), Some(deadline))?, CommandOutcome::Applied);
+        /// assert_eq!(service.expires_at(&Slug::from("sale")), Some(deadline));
+        /// assert_eq!(service.handle_set_expiry(Slug::from("sale"), Some(deadline))?, CommandOutcome::Unchanged);
+        ///
+        /// service.handle_set_expiry(Slug::from("sale"), None)?;
+        /// assert_eq!(service.expires_at(&Slug::from("sale")), None);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_set_expiry(
+            &mut self,
+            slug: Slug,
+            expires_at: Option<SystemTime>,
+        ) -> Result<CommandOutcome, ShortenerError>;
+
+        /// Makes an existing link expire `ttl` from now, as told by the
+        /// service's clock rather than the caller's, and records the
+        /// resulting absolute time like
+        /// [`ExtendedCommandHandler::handle_set_expiry`]. A TTL reaching
+        /// beyond representable time never elapses, so the link is made to
+        /// never expire. To create a link with a TTL in one call, see
+        /// [`ExtendedCommandHandler::handle_create_with_ttl`].
+        ///
+        /// ## Errors
+        ///
+        /// See [`ExtendedCommandHandler::handle_set_expiry`].
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("sale")))?;
+        ///
+        /// let month = Duration::from_secs(30 * 24 * 3_600);
+        /// service.handle_set_expiry_in(Slug::from("sale"), month)?;
+        /// assert_eq!(service.expires_at(&Slug::from("sale")), Some(now + month));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn handle_set_expiry_in(
+            &mut self,
+            slug: Slug,
+            ttl: Duration,
+        ) -> Result<CommandOutcome, ShortenerError>;
+    }
+}
+
+/// The event sourcing plumbing behind [`UrlShortenerService`], for commands
+/// defined outside this crate. A custom command restores a link's
+/// [`ShortLinkAggregate`] from the service,
+/// runs the built-in commands or records [`EventType::Custom`] events, and
+/// interprets those in a projection registered with
+/// [`UrlShortenerService::with_custom_projection`].
+#[cfg(feature = "extension")]
+pub mod extension {
+    pub use super::domain::{EventBroker, LinkState, ShortLinkAggregate, Snapshot};
+    pub use super::events::Event;
+}
+
+/// Queries for CQRS
+pub mod queries {
+    use super::{BulkOutcome, CursorError, ItemError, LinkPage, SharedStats, ShortenerError, Slug, Stats, Url};
+
+    /// Trait for query handlers.
+    pub trait QueryHandler {
+        /// Returns the [`Stats`] for a specific [`ShortLink`], such as the
+        /// number of redirects (clicks).
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::QueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        /// service.handle_redirect(Slug::from("docs"))?;
+        ///
+        /// let stats = service.get_stats(Slug::from("docs"))?;
+        /// assert_eq!(stats.link.url, Url::from("https://example.com"));
+        /// assert_eq!(stats.redirects, 1);
+        ///
+        /// assert_eq!(service.get_stats(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`ShortLink`]: super::ShortLink
+        fn get_stats(&self, slug: Slug) -> Result<Stats, ShortenerError>;
+    }
+
+    /// Queries beyond [`QueryHandler`], kept in their own trait so that
+    /// implementing the original contract doesn't require them.
+    pub trait ExtendedQueryHandler: QueryHandler {
+        /// Returns the [`Stats`] of several links at once, like
+        /// [`QueryHandler::get_stats`] for each slug. The items are in the
+        /// order of `slugs`, each failure summarized by its slug.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+        ///
+        /// let batch = service.get_stats_batch(&[Slug::from("missing"), Slug::from("docs")]);
+        /// let failed = batch.items[0].as_ref().unwrap_err();
+        /// assert_eq!((failed.input_summary.as_str(), &failed.error), ("missing", &ShortenerError::SlugNotFound));
+        /// assert_eq!(batch.items[1].as_ref().map(|stats| stats.redirects), Ok(0));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn get_stats_batch(&self, slugs: &[Slug]) -> BulkOutcome<Stats> {
+            let items = slugs.iter()
+                .enumerate()
+                .map(|(index, slug)| {
+                    self.get_stats(slug.clone())
+                        .map_err(|error| ItemError { index, input_summary: slug.0.clone(), error })
+                })
+                .collect();
+
+            BulkOutcome { items }
+        }
+
+        /// Returns a page of at most `limit` short links with their
+        /// [`Stats`], in the order they were created, starting after the
+        /// link `cursor` points to or at the oldest link without one.
+        ///
+        /// Cursors are opaque strings safe to hand to web clients. They
+        /// point at the immutable creation sequence of the last link of a
+        /// page rather than an offset, so links deleted while a client pages
+        /// neither shift later pages nor show up in them, and every link
+        /// that survives is returned exactly once. Cursors are signed, see
+        /// [`UrlShortenerService::with_cursor_key`](super::UrlShortenerService::with_cursor_key).
+        ///
+        /// ## Errors
+        ///
+        /// A [`CursorError`] if `cursor` wasn't issued by this service.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{CursorError, ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// for slug in ["c", "a", "b"] {
+        ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+        /// }
+        ///
+        /// let first = service.list_links(None, 2).unwrap();
+        /// assert_eq!(first.links.len(), 2);
+        /// service.handle_delete_short_link(Slug::from("c"))?;
+        ///
+        /// let second = service.list_links(first.next.as_deref(), 2).unwrap();
+        /// assert_eq!(second.links[0].link.slug, Slug::from("b"));
+        /// assert_eq!(second.next, None);
+        /// assert_eq!(service.list_links(Some("page-2"), 2), Err(CursorError::Malformed));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn list_links(&self, cursor: Option<&str>, limit: usize) -> Result<LinkPage, CursorError>;
+
+        /// Returns the slugs of all existing links pointing at `url`,
+        /// compared in normalized form and sorted. Empty if there are none.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("b")))?;
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("a")))?;
+        ///
+        /// assert_eq!(service.find_by_url(Url::from("HTTPS://Example.com/")), [Slug::from("a"), Slug::from("b")]);
+        /// assert!(service.find_by_url(Url::from("https://example.org")).is_empty());
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn find_by_url(&self, url: Url) -> Vec<Slug>;
+
+        /// Returns the number of redirects across all existing links,
+        /// saturating at `u64::MAX`.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// assert_eq!(service.total_redirects(), 0);
+        ///
+        /// for slug in ["a", "b"] {
+        ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+        ///     service.handle_redirect(Slug::from(slug))?;
+        /// }
+        /// assert!(service.handle_redirect(Slug::from("missing")).is_err());
+        /// assert_eq!(service.total_redirects(), 2);
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn total_redirects(&self) -> u64;
+
+        /// Returns the [`Stats`] of the `n` most redirected links, most
+        /// redirected first and ties broken by slug; all links if there are
+        /// fewer than `n`.
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use url_shortener::commands::CommandHandler;
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+        ///
+        /// let mut service = UrlShortenerService::new();
+        /// for (slug, redirects) in [("a", 1), ("b", 3), ("c", 1)] {
+        ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+        ///     for _ in 0..redirects {
+        ///         service.handle_redirect(Slug::from(slug))?;
+        ///     }
+        /// }
+        ///
+        /// let top = service.top_links(2);
+        /// let ranked: Vec<_> = top.iter().map(|stats| (stats.link.slug.0.as_str(), stats.redirects)).collect();
+        /// assert_eq!(ranked, [("b", 3), ("a", 1)]);
+        /// assert!(service.top_links(0).is_empty());
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        fn top_links(&self, n: usize) -> Vec<Stats>;
+
+        /// Returns the stats of the link a token was issued for, with what
+        /// its scope includes, checking its expiry against the service's
+        /// clock. Tokens are issued with
+        /// [`ExtendedCommandHandler::handle_issue_stats_token`] and stop
+        /// working when they expire, are revoked or their link is deleted.
+        ///
+        /// ## Errors
+        ///
+        /// [`ShortenerError::InvalidStatsToken`] if the token doesn't grant
+        /// access (anymore).
+        ///
+        /// ## Examples
+        ///
+        /// ```
+        /// use std::time::{Duration, SystemTime};
+        /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+        /// use url_shortener::queries::ExtendedQueryHandler;
+        /// use url_shortener::{FixedClock, ShortenerError, Slug, StatsScope, Url, UrlShortenerService};
+        ///
+        /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+        /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("promo")))?;
+        ///
+        /// let scope = StatsScope { outcomes: true, ..StatsScope::default() };
+        /// let token = service.handle_issue_stats_token(Slug::from("promo"), now + Duration::from_secs(60), scope)?;
+        /// let shared = service.get_stats_with_token(token.as_str())?;
+        /// assert!(shared.outcomes.is_some() && shared.destinations.is_none());
+        ///
+        /// let expired = service.handle_issue_stats_token(Slug::from("promo"), now, scope)?;
+        /// assert_eq!(service.get_stats_with_token(expired.as_str()), Err(ShortenerError::InvalidStatsToken));
+        /// # Ok::<(), ShortenerError>(())
+        /// ```
+        ///
+        /// [`ExtendedCommandHandler::handle_issue_stats_token`]:
+        /// super::commands::ExtendedCommandHandler::handle_issue_stats_token
+        fn get_stats_with_token(&self, token: &str) -> Result<SharedStats, ShortenerError>;
+    }
+}
+
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime};
+use events::Event;
+pub use events::{EventType, LookupNormalization};
+
+/// Result of a dry-run [`UrlShortenerService::validate_create`] call.
+#[derive(Debug, PartialEq)]
+pub struct ValidationReport {
+    /// Every check that failed, in the order the real command runs them.
+    pub issues: Vec<ShortenerError>,
+}
+
+impl ValidationReport {
+    /// Returns `true` if the create command would succeed.
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns the [`ShortenerError::code`] of every issue.
+    pub fn codes(&self) -> Vec<&'static str> {
+        self.issues.iter().map(ShortenerError::code).collect()
+    }
+}
+
+/// How a redirect attempt ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RedirectOutcome {
+    /// The client was redirected.
+    Success,
+
+    /// The slug doesn't map to any short link.
+    NotFound,
+
+    /// The link is marked dead and its policy refuses redirects.
+    Dead,
+
+    /// The link is past its expiry.
+    Expired,
+
+    /// The link is disabled.
+    Disabled,
+
+    /// The link reached its redirect limit.
+    Exhausted,
+}
+
+/// Counters of redirect attempts by [`RedirectOutcome`].
+///
+/// Outcomes are counted in memory as attempts are made; they are not
+/// recorded as events, so failed attempts cost no storage and the counters
+/// start from zero whenever the service does.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OutcomeStats {
+    counts: HashMap<RedirectOutcome, u64>,
+}
+
+impl OutcomeStats {
+    /// Returns the number of attempts that ended with the given outcome.
+    pub fn get(&self, outcome: RedirectOutcome) -> u64 {
+        self.counts.get(&outcome).copied().unwrap_or(0)
+    }
+
+    /// Returns the number of all attempts.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    fn record(&mut self, outcome: RedirectOutcome) {
+        *self.counts.entry(outcome).or_insert(0) += 1;
+    }
+}
+
+/// What a serving layer should respond to a redirect request, as decided by
+/// [`UrlShortenerService::decide_redirect`].
+#[derive(Debug, PartialEq)]
+pub enum RedirectDecision {
+    /// Redirect the client to the link's URL, applying the hints.
+    Redirect {
+        /// The resolved short link.
+        link: ShortLink,
+
+        /// How to shape the redirect response.
+        hints: ServingHints,
+    },
+
+    /// Show a warning before redirecting, because the destination was found
+    /// dead.
+    Interstitial {
+        /// The resolved short link.
+        link: ShortLink,
+
+        /// How to shape the redirect response.
+        hints: ServingHints,
+
+        /// Why the destination is considered dead.
+        evidence: String,
+    },
+
+    /// Don't redirect; respond with an error.
+    Error {
+        /// Why the redirect was refused.
+        error: ShortenerError,
+
+        /// Suggested HTTP status of the response.
+        status: u16,
+    },
+}
+
+/// What a serving layer actually served for a redirect it answered with
+/// [`UrlShortenerService::resolve_url_borrowed`], handed back to
+/// [`UrlShortenerService::record_click`] once the response went out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RedirectContext {
+    /// The destination returned to the client.
+    pub served_url: Url,
+
+    /// How the requested slug was resolved to the stored one.
+    pub lookup: LookupNormalization,
+}
+
+impl RedirectContext {
+    /// A context for `served_url`, resolved from an exactly matching slug.
+    pub fn new(served_url: impl Into<Url>) -> Self {
+        RedirectContext { served_url: served_url.into(), lookup: LookupNormalization::Exact }
+    }
+
+    /// Sets how the requested slug was resolved.
+    pub fn with_lookup(mut self, lookup: LookupNormalization) -> Self {
+        self.lookup = lookup;
+        self
+    }
+}
+
+/// A secret granting read access to the stats of one link, issued by
+/// [`ExtendedCommandHandler::handle_issue_stats_token`](commands::ExtendedCommandHandler::handle_issue_stats_token).
+///
+/// Only a hash of the token is recorded, so it is shown once, when issued.
+/// Its `Debug` output hides it.
+#[derive(Clone, PartialEq, Eq)]
+pub struct StatsToken(String);
+
+impl StatsToken {
+    /// The token to hand to the holder, e.g. in a share URL.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for StatsToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StatsToken(..)")
+    }
+}
+
+/// What a [`StatsToken`] reveals besides the link's [`Stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StatsScope {
+    /// Include the link's redirect outcomes, see
+    /// [`UrlShortenerService::get_outcome_stats`].
+    pub outcomes: bool,
+
+    /// Include the link's redirects per destination, see
+    /// [`UrlShortenerService::get_redirects_by_url`].
+    pub destinations: bool,
+}
+
+/// The stats of a link as shown to the holder of a [`StatsToken`], see
+/// [`ExtendedQueryHandler::get_stats_with_token`](queries::ExtendedQueryHandler::get_stats_with_token).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedStats {
+    /// The link's stats.
+    pub stats: Stats,
+
+    /// The link's redirect outcomes, if the token's scope includes them.
+    pub outcomes: Option<OutcomeStats>,
+
+    /// The link's redirects per destination, if the token's scope includes
+    /// them.
+    pub destinations: Option<Vec<(Url, u64)>>,
+}
+
+/// Distribution of the time between consecutive redirects of a link, as
+/// returned by [`UrlShortenerService::get_click_interval_stats`].
+/// Percentiles use the nearest-rank method, so each is one of the measured
+/// intervals.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalStats {
+    /// Median interval.
+    pub p50: Duration,
+
+    /// 90th percentile interval.
+    pub p90: Duration,
+
+    /// 99th percentile interval.
+    pub p99: Duration,
+
+    /// Longest interval.
+    pub max: Duration,
+
+    /// Number of intervals measured, one less than the clicks they lie
+    /// between.
+    pub count: usize,
+}
+
+/// Result of [`UrlShortenerService::get_click_interval_stats`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClickIntervals {
+    /// Fewer than two clicks were considered, so there is no interval.
+    InsufficientData {
+        /// Number of clicks considered.
+        clicks: usize,
+    },
+
+    /// The intervals between the clicks considered.
+    Measured(IntervalStats),
+}
+
+/// When a link expires, as returned by [`UrlShortenerService::expiry`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkExpiry {
+    /// The absolute time the link stops redirecting.
+    pub expires_at: SystemTime,
+
+    /// Time left until then by the service's clock, zero once expired.
+    pub remaining: Duration,
+}
+
+/// One recorded event of a short link, as returned by
+/// [`UrlShortenerService::get_events`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EventRecord {
+    /// Zero-based position of the event in the link's stream.
+    pub position: usize,
+
+    /// When the event was recorded, according to the service's [`Clock`].
+    pub timestamp: SystemTime,
+
+    /// What happened.
+    pub event_type: EventType,
+}
+
+/// One event of the service-wide stream, as returned by
+/// [`UrlShortenerService::events_since`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SequencedEvent {
+    /// Position of the event in the service-wide stream, starting at 1.
+    pub sequence: u64,
+
+    /// The link the event belongs to.
+    pub slug: Slug,
+
+    /// Compact id of [`SequencedEvent::slug`].
+    pub slug_id: SlugId,
+
+    /// When the event was recorded, according to the service's [`Clock`].
+    pub timestamp: SystemTime,
+
+    /// What happened.
+    pub event_type: EventType,
+}
+
+/// One event of the service-wide stream borrowed from the event store, as
+/// returned by [`UrlShortenerService::stream_all_events`]. Serializes like
+/// [`SequencedEvent`] without cloning the payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EventView<'a> {
+    /// Position of the event in the service-wide stream, starting at 1.
+    pub sequence: u64,
+
+    /// The link the event belongs to.
+    pub slug: &'a Slug,
+
+    /// Compact id of [`EventView::slug`].
+    pub slug_id: SlugId,
+
+    /// When the event was recorded, according to the service's [`Clock`].
+    pub timestamp: SystemTime,
+
+    /// What happened.
+    pub event_type: &'a EventType,
+}
+
+/// Version of the canonical form written by [`EventView::to_canonical_json`],
+/// part of each `schema` field.
+#[cfg(feature = "serde")]
+pub const CANONICAL_SCHEMA_VERSION: u32 = 1;
+
+#[cfg(feature = "serde")]
+impl EventView<'_> {
+    /// Serializes the event in canonical form, the same bytes for the same
+    /// event whatever the features enabled, so consumers can sign and diff
+    /// it. A JSON object without whitespace whose fields come in this order:
+    ///
+    /// 1. `schema`: `"url-shortener/<event type>/v<version>"`, e.g.
+    ///    `"url-shortener/ShortLinkCreated/v1"`, see
+    ///    [`CANONICAL_SCHEMA_VERSION`];
+    /// 2. `sequence`;
+    /// 3. `slug`;
+    /// 4. `slug_id`;
+    /// 5. `timestamp`;
+    /// 6. `event_type`, serialized as by the `serde` feature.
+    ///
+    /// Within `event_type`, object keys are sorted, optional fields are
+    /// present as `null` and timestamps, like `timestamp` itself, are RFC
+    /// 3339 strings in UTC, with fractional seconds only if non-zero, e.g.
+    /// `"2024-01-01T00:00:00.5Z"`.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(std::time::SystemTime::UNIX_EPOCH));
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// assert_eq!(
+    ///     service.stream_all_events().next().unwrap().to_canonical_json(),
+    ///     concat!(
+    ///         r#"{"schema":"url-shortener/ShortLinkCreated/v1","sequence":1,"slug":"docs","slug_id":0,"#,
+    ///         r#""timestamp":"1970-01-01T00:00:00Z","#,
+    ///         r#""event_type":{"ShortLinkCreated":{"expires_at":null,"url":"https://example.com"}}}"#
+    ///     )
+    /// );
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn to_canonical_json(&self) -> String {
+        let event_type = serde_json::to_value(self.event_type).expect("events always serialize");
+        let name = match &event_type {
+            serde_json::Value::String(name) => name.as_str(),
+            serde_json::Value::Object(variant) => variant.keys().next().expect("variants are tagged"),
+            _ => unreachable!("event types serialize as tagged variants")
+        };
+
+        let mut out = String::new();
+        out.push_str("{\"schema\":");
+        write_canonical(&format!("url-shortener/{name}/v{CANONICAL_SCHEMA_VERSION}").into(), &mut out);
+        out.push_str(&format!(",\"sequence\":{},\"slug\":", self.sequence));
+        write_canonical(&self.slug.0.as_str().into(), &mut out);
+        out.push_str(&format!(",\"slug_id\":{},\"timestamp\":\"{}\"", self.slug_id.0, rfc3339(self.timestamp)));
+        out.push_str(",\"event_type\":");
+        write_canonical(&event_type, &mut out);
+        out.push('}');
+        out
+    }
+
+    /// Serializes a batch of events, e.g. a chunk of
+    /// [`UrlShortenerService::for_each_event_chunk`], in canonical form: an
+    /// object with the `schema` `"url-shortener/EventBatch/v<version>"`
+    /// followed by `events`, the array of the events in the order given,
+    /// each as by [`EventView::to_canonical_json`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{EventView, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let events: Vec<_> = service.stream_all_events().collect();
+    /// let batch = EventView::canonical_batch_json(&events);
+    /// assert!(batch.starts_with(r#"{"schema":"url-shortener/EventBatch/v1","events":[{"schema":"#));
+    /// assert!(batch.contains(&events[1].to_canonical_json()));
+    /// assert_eq!(EventView::canonical_batch_json(&[]), r#"{"schema":"url-shortener/EventBatch/v1","events":[]}"#);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn canonical_batch_json(events: &[EventView<'_>]) -> String {
+        let mut out = format!("{{\"schema\":\"url-shortener/EventBatch/v{CANONICAL_SCHEMA_VERSION}\",\"events\":[");
+        for (i, event) in events.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(&event.to_canonical_json());
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Writes `value` without whitespace, with object keys sorted and serialized
+/// [`SystemTime`]s as RFC 3339 strings.
+#[cfg(feature = "serde")]
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(fields) => {
+            if let (2, Some(secs), Some(nanos)) = (
+                fields.len(),
+                fields.get("secs_since_epoch").and_then(Value::as_u64),
+                fields.get("nanos_since_epoch").and_then(Value::as_u64)
+            ) {
+                let time = SystemTime::UNIX_EPOCH + Duration::new(secs, nanos as u32);
+                out.push_str(&format!("\"{}\"", rfc3339(time)));
+                return;
+            }
+
+            let mut keys: Vec<&String> = fields.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&serde_json::to_string(key).expect("strings always serialize"));
+                out.push(':');
+                write_canonical(&fields[key], out);
+            }
+            out.push('}');
+        }
+        _ => out.push_str(&serde_json::to_string(value).expect("JSON values always serialize"))
+    }
+}
+
+/// Formats `time` as an RFC 3339 timestamp in UTC, with fractional seconds
+/// only if non-zero.
+#[cfg(feature = "serde")]
+fn rfc3339(time: SystemTime) -> String {
+    let nanos = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(after) => after.as_nanos() as i128,
+        Err(before) => -(before.duration().as_nanos() as i128)
+    };
+    let secs = nanos.div_euclid(1_000_000_000) as i64;
+    let fraction = nanos.rem_euclid(1_000_000_000);
+
+    // Days since the epoch to a civil date, after Howard Hinnant's
+    // `civil_from_days`.
+    let days = secs.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let second_of_day = secs.rem_euclid(86_400);
+    let mut formatted = format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}",
+        second_of_day / 3_600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    );
+    if fraction > 0 {
+        formatted.push_str(format!(".{fraction:09}").trim_end_matches('0'));
+    }
+    formatted.push('Z');
+    formatted
+}
+
+/// An event of a [`TraceReport`] with the link's [`Stats`] just before and
+/// just after it was projected, `None` while the link doesn't exist.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TracedEvent {
+    /// Position of the event in the service-wide stream, starting at 1.
+    pub sequence: u64,
+
+    /// When the event was recorded, according to the service's [`Clock`].
+    pub timestamp: SystemTime,
+
+    /// What happened.
+    pub event_type: EventType,
+
+    /// The link's stats before the event.
+    pub before: Option<Stats>,
+
+    /// The link's stats after the event.
+    pub after: Option<Stats>,
+}
+
+impl TracedEvent {
+    /// Describes what the event changed in the link's stats.
+    fn deltas(&self) -> Vec<String> {
+        match (&self.before, &self.after) {
+            (None, None) => Vec::new(),
+            (None, Some(_)) => vec!["created".to_string()],
+            (Some(_), None) => vec!["deleted".to_string()],
+            (Some(before), Some(after)) => {
+                let mut deltas = Vec::new();
+                if before.redirects != after.redirects {
+                    deltas.push(format!("redirects {} -> {}", before.redirects, after.redirects));
+                }
+                if before.enabled != after.enabled {
+                    deltas.push(format!("enabled {} -> {}", before.enabled, after.enabled));
+                }
+                if before.link.url != after.link.url {
+                    deltas.push(format!("url {} -> {}", before.link.url.0, after.link.url.0));
+                }
+                if before.last_redirected_at != after.last_redirected_at {
+                    deltas.push("last redirect moved".to_string());
+                }
+                deltas
+            }
+        }
+    }
+}
+
+/// The events of a link around a point in time with the change each made to
+/// the link's stats, as returned by [`UrlShortenerService::trace`]. Its
+/// `Display` output lists one event per line.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TraceReport {
+    /// The traced link.
+    pub slug: Slug,
+
+    /// The time the trace is centered on.
+    pub around: SystemTime,
+
+    /// The traced events, in the order they were recorded.
+    pub events: Vec<TracedEvent>,
+}
+
+impl std::fmt::Display for TraceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "trace of {} ({} events)", self.slug, self.events.len())?;
+        for event in &self.events {
+            let at = event.timestamp.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+            let deltas = event.deltas();
+            let deltas = if deltas.is_empty() { "no change".to_string() } else { deltas.join(", ") };
+            let (secs, millis) = (at.as_secs(), at.subsec_millis());
+            writeln!(f, "#{} at {secs}.{millis:03}s {:?}: {deltas}", event.sequence, event.event_type)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Overall figures of a service, as returned by
+/// [`UrlShortenerService::totals`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Totals {
+    /// Number of existing links.
+    pub links: u64,
+
+    /// Sum of the redirect counts of all existing links, saturating at
+    /// `u64::MAX`.
+    pub redirects: u64,
+}
+
+/// The most redirected links, as returned by
+/// [`UrlShortenerService::leaderboard`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Leaderboard {
+    /// Links with their redirect counts, most redirected first and ties
+    /// broken by slug. Links that never redirected aren't ranked.
+    pub entries: Vec<(Slug, u64)>,
+
+    /// Number of incremental updates applied since the last
+    /// [`UrlShortenerService::reconcile_leaderboards`].
+    pub updates_since_reconcile: u64,
+}
+
+/// A page of links as returned by
+/// [`ExtendedQueryHandler::list_links`](queries::ExtendedQueryHandler::list_links).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkPage {
+    /// The links of the page with their stats, oldest first.
+    pub links: Vec<Stats>,
+
+    /// Cursor of the next page, `None` if no link was created after the
+    /// last one of this page.
+    pub next: Option<String>,
+}
+
+/// Why a page cursor was refused by
+/// [`ExtendedQueryHandler::list_links`](queries::ExtendedQueryHandler::list_links).
+#[derive(Debug, Clone, PartialEq)]
+pub enum CursorError {
+    /// The string is not a cursor.
+    Malformed,
+
+    /// The cursor was issued in a format this build doesn't read.
+    UnsupportedVersion(u32),
+
+    /// The cursor was altered, or issued under another cursor key.
+    Tampered,
+}
+
+impl std::fmt::Display for CursorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CursorError::Malformed => write!(f, "the page cursor is malformed"),
+            CursorError::UnsupportedVersion(version) => write!(f, "page cursor version {version} is not supported"),
+            CursorError::Tampered => write!(f, "the page cursor was not issued by this service"),
+        }
+    }
+}
+
+impl std::error::Error for CursorError {}
+
+/// Format version of page cursors.
+const CURSOR_VERSION: u32 = 1;
+
+/// What happens when an event is published while the projection queue is
+/// full. There is no blocking variant: the queue is drained by the same
+/// thread that publishes, so waiting for room could never end.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QueueOverflow {
+    /// Discard the new event for the deferred projections and count it in
+    /// [`ProjectionQueueStatus::dropped`]. Those projections lag until the
+    /// next reconcile.
+    DropWithCounter,
+
+    /// Apply the oldest queued event first to make room.
+    AutoPump,
+}
+
+/// Configuration of the deferred projection queue, see
+/// [`UrlShortenerService::with_projection_queue`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProjectionQueueConfig {
+    /// Maximum number of pending events.
+    pub capacity: usize,
+
+    /// What happens to events published while the queue is full.
+    pub overflow: QueueOverflow,
+}
+
+/// State of the deferred projection queue, see
+/// [`UrlShortenerService::with_projection_queue`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectionQueueStatus {
+    /// Events waiting for [`UrlShortenerService::pump_projections`].
+    pub pending: usize,
+
+    /// Maximum number of pending events.
+    pub capacity: usize,
+
+    /// Events discarded under [`QueueOverflow::DropWithCounter`].
+    pub dropped: u64,
+}
+
+struct ProjectionQueue {
+    events: VecDeque<Event>,
+    capacity: usize,
+    overflow: QueueOverflow,
+    dropped: u64
+}
+
+/// Existing links by their creation sequence, the number of links created
+/// before them. Sequences are never reused, so a page cursor holding one
+/// stays valid while links around it are deleted.
+#[derive(Debug, Default, PartialEq)]
+struct CreationOrder {
+    created: u64,
+    links: BTreeMap<u64, Slug>,
+    sequences: HashMap<Slug, u64>
+}
+
+impl CreationOrder {
+    fn insert(&mut self, slug: &Slug) {
+        self.links.insert(self.created, slug.clone());
+        self.sequences.insert(slug.clone(), self.created);
+        self.created += 1;
+    }
+
+    fn remove(&mut self, slug: &Slug) {
+        if let Some(sequence) = self.sequences.remove(slug) {
+            self.links.remove(&sequence);
+        }
+    }
+
+    /// Replays the creations and deletions of `events`.
+    #[cfg(feature = "serde")]
+    fn from_events(events: &[Event]) -> Self {
+        let mut order = CreationOrder::default();
+        for event in events {
+            match event.event_type {
+                EventType::ShortLinkCreated { .. } => order.insert(&event.slug),
+                EventType::ShortLinkDeleted => order.remove(&event.slug),
+                _ => {}
+            }
+        }
+        order
+    }
+}
+
+/// Protection and tags of seeded links, see
+/// [`UrlShortenerService::bootstrap`]. A reserved slug keeps its entry after
+/// the link is deleted.
+#[derive(Debug, Default, PartialEq)]
+struct SeededLinks {
+    protections: HashMap<Slug, LinkProtection>,
+    tags: HashMap<Slug, Vec<String>>
+}
+
+impl SeededLinks {
+    fn apply(&mut self, event: &Event) {
+        match &event.event_type {
+            EventType::ProtectionSet(protection) => {
+                self.protections.insert(event.slug.clone(), *protection);
+            }
+            EventType::TagsSet(tags) => {
+                self.tags.insert(event.slug.clone(), tags.clone());
+            }
+            EventType::ShortLinkDeleted => {
+                self.tags.remove(&event.slug);
+                match self.protections.get_mut(&event.slug) {
+                    Some(protection) if protection.reserved => protection.undeletable = false,
+                    _ => {
+                        self.protections.remove(&event.slug);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replays the seed events of `events`.
+    #[cfg(feature = "serde")]
+    fn from_events(events: &[Event]) -> Self {
+        let mut seeded = SeededLinks::default();
+        for event in events {
+            seeded.apply(event);
+        }
+        seeded
+    }
+}
+
+/// Outstanding stats tokens by their hash, see
+/// [`ExtendedCommandHandler::handle_issue_stats_token`](commands::ExtendedCommandHandler::handle_issue_stats_token).
+/// Expired tokens stay until revoked or their link is deleted.
+#[derive(Debug, Default, PartialEq)]
+struct StatsGrants {
+    grants: HashMap<u64, StatsGrant>
+}
+
+#[derive(Debug, PartialEq)]
+struct StatsGrant {
+    slug: Slug,
+    expires_at: SystemTime,
+    scope: StatsScope
+}
+
+impl StatsGrants {
+    fn apply(&mut self, event: &Event) {
+        match &event.event_type {
+            EventType::StatsTokenIssued { token_hash, expires_at, scope } => {
+                let grant = StatsGrant { slug: event.slug.clone(), expires_at: *expires_at, scope: *scope };
+                self.grants.insert(*token_hash, grant);
+            }
+            EventType::StatsTokenRevoked { token_hash } => {
+                self.grants.remove(token_hash);
+            }
+            EventType::ShortLinkDeleted => self.grants.retain(|_, grant| grant.slug != event.slug),
+            _ => {}
+        }
+    }
+
+    /// Replays the token events of `events`.
+    #[cfg(feature = "serde")]
+    fn from_events(events: &[Event]) -> Self {
+        let mut grants = StatsGrants::default();
+        for event in events {
+            grants.apply(event);
+        }
+        grants
+    }
+
+    /// Hashes a token the way it is recorded. SipHash with fixed keys, so
+    /// hashes stay the same across restarts.
+    fn hash(token: &str) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        ("stats-token", token).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Makes up a new 128-bit token from the process's random hash keys.
+    fn generate(salt: usize) -> StatsToken {
+        let random = RandomState::new();
+        let (high, low) = (random.hash_one((salt, 0u8)), random.hash_one((salt, 1u8)));
+
+        StatsToken(format!("st_{high:016x}{low:016x}"))
+    }
+}
+
+/// Timestamps of the latest redirects of each existing link, at most
+/// `capacity` per link, see [`UrlShortenerService::get_click_interval_stats`].
+#[derive(Debug, PartialEq)]
+struct RecentClicks {
+    capacity: usize,
+    clicks: HashMap<Slug, VecDeque<SystemTime>>
+}
+
+impl Default for RecentClicks {
+    fn default() -> Self {
+        RecentClicks { capacity: DEFAULT_RECENT_CLICKS, clicks: HashMap::new() }
+    }
+}
+
+impl RecentClicks {
+    fn apply(&mut self, event: &Event) {
+        match event.event_type {
+            EventType::ShortLinkRedirected { .. } if self.capacity > 0 => {
+                let clicks = self.clicks.entry(event.slug.clone()).or_default();
+                if clicks.len() == self.capacity {
+                    clicks.pop_front();
+                }
+                clicks.push_back(event.timestamp);
+            }
+            EventType::ShortLinkCreated { .. } | EventType::ShortLinkDeleted => {
+                self.clicks.remove(&event.slug);
+            }
+            _ => {}
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        for clicks in self.clicks.values_mut() {
+            let excess = clicks.len().saturating_sub(capacity);
+            clicks.drain(..excess);
+        }
+        self.clicks.retain(|_, clicks| !clicks.is_empty());
+    }
+
+    /// Replays the redirects of `events`.
+    #[cfg(feature = "serde")]
+    fn from_events(events: &[Event]) -> Self {
+        let mut recent = RecentClicks::default();
+        for event in events {
+            recent.apply(event);
+        }
+        recent
+    }
+}
+
+/// Existing links with an expiry ordered by it, and the lead times each was
+/// already notified for since its expiry was last set, see
+/// [`UrlShortenerService::expiring_within`].
+#[derive(Debug, Default, PartialEq)]
+struct ExpiryOrder {
+    expiries: HashMap<Slug, SystemTime>,
+    links: BTreeSet<(SystemTime, Slug)>,
+    notified: HashMap<Slug, HashSet<Duration>>
+}
+
+impl ExpiryOrder {
+    fn apply(&mut self, event: &Event) {
+        match event.event_type {
+            EventType::ShortLinkCreated { expires_at, .. } | EventType::ExpirySet(expires_at) => {
+                self.remove(&event.slug);
+                if let Some(expires_at) = expires_at {
+                    self.expiries.insert(event.slug.clone(), expires_at);
+                    self.links.insert((expires_at, event.slug.clone()));
+                }
+            }
+            EventType::ExpiryNoticeSent(lead) => {
+                self.notified.entry(event.slug.clone()).or_default().insert(lead);
+            }
+            EventType::ShortLinkDeleted => self.remove(&event.slug),
+            _ => {}
+        }
+    }
+
+    fn remove(&mut self, slug: &Slug) {
+        if let Some(expires_at) = self.expiries.remove(slug) {
+            self.links.remove(&(expires_at, slug.clone()));
+        }
+        self.notified.remove(slug);
+    }
+
+    fn notified(&self, slug: &Slug, lead: Duration) -> bool {
+        self.notified.get(slug).is_some_and(|leads| leads.contains(&lead))
+    }
+
+    /// Replays the expiries and notices of `events`.
+    #[cfg(feature = "serde")]
+    fn from_events(events: &[Event]) -> Self {
+        let mut order = ExpiryOrder::default();
+        for event in events {
+            order.apply(event);
+        }
+        order
+    }
+}
+
+/// The event store, keyed by interned [`SlugId`]s. A command hashes its slug
+/// once to find the id; the positions of the link's events and its snapshot
+/// are then looked up by index.
+#[derive(Default)]
+struct ResolveIndex {
+    ids: HashMap<Slug, SlugId>,
+    slugs: Vec<Slug>,
+    positions: Vec<Vec<usize>>,
+    snapshots: Vec<Option<domain::Snapshot>>,
+    /// The id of each event in the log, by position.
+    event_ids: Vec<SlugId>,
+    /// Number of slug lookups, so tests can count the hashes of a command.
+    #[cfg(test)]
+    lookups: std::cell::Cell<usize>
+}
+
+impl ResolveIndex {
+    /// Returns the id of a slug that has events.
+    fn get(&self, slug: &Slug) -> Option<SlugId> {
+        #[cfg(test)]
+        self.lookups.set(self.lookups.get() + 1);
+
+        self.ids.get(slug).copied()
+    }
+
+    /// Returns the id of a slug, handing out the next one if it has none.
+    fn intern(&mut self, slug: &Slug) -> SlugId {
+        if let Some(id) = self.get(slug) {
+            return id;
+        }
+
+        let id = SlugId(self.slugs.len() as u64);
+        self.ids.insert(slug.clone(), id);
+        self.slugs.push(slug.clone());
+        self.positions.push(Vec::new());
+        self.snapshots.push(None);
+
+        id
+    }
+
+    /// Records that the event at `position` of the log belongs to `id`.
+    fn push(&mut self, id: SlugId, position: usize) {
+        self.positions[id.0 as usize].push(position);
+        self.event_ids.push(id);
+    }
+
+    fn slug(&self, id: SlugId) -> Option<&Slug> {
+        self.slugs.get(id.0 as usize)
+    }
+
+    fn positions(&self, id: SlugId) -> &[usize] {
+        self.positions.get(id.0 as usize).map_or(&[], Vec::as_slice)
+    }
+
+    fn snapshot(&self, id: SlugId) -> Option<&domain::Snapshot> {
+        self.snapshots.get(id.0 as usize)?.as_ref()
+    }
+
+    fn set_snapshot(&mut self, id: SlugId, snapshot: domain::Snapshot) {
+        self.snapshots[id.0 as usize] = Some(snapshot);
+    }
+
+    fn clear_snapshots(&mut self) {
+        self.snapshots.iter_mut().for_each(|snapshot| *snapshot = None);
+    }
+
+    /// Iterates the slugs with events and the positions of their events, in
+    /// the order the slugs were interned.
+    fn iter(&self) -> impl Iterator<Item = (&Slug, &[usize])> {
+        self.slugs.iter().zip(self.positions.iter().map(Vec::as_slice))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.slugs.is_empty()
+    }
+}
+
+/// Failure of a single item of a bulk operation.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ItemError {
+    /// Position of the failed item in the input.
+    pub index: usize,
+
+    /// Short description of the failed input, suitable for API responses.
+    pub input_summary: String,
+
+    /// Reason of the failure.
+    pub error: ShortenerError,
+}
+
+/// Per-item results of a bulk operation, positionally aligned with its input.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BulkOutcome<T> {
+    /// One result per input item, in input order.
+    pub items: Vec<Result<T, ItemError>>,
+}
+
+impl<T> BulkOutcome<T> {
+    /// Returns the values of all successful items, in input order.
+    pub fn succeeded(&self) -> impl Iterator<Item = &T> {
+        self.items.iter().filter_map(|item| item.as_ref().ok())
+    }
+
+    /// Returns the errors of all failed items, in input order.
+    pub fn failed(&self) -> impl Iterator<Item = &ItemError> {
+        self.items.iter().filter_map(|item| item.as_ref().err())
+    }
+
+    /// Returns `true` if no item failed.
+    pub fn is_all_ok(&self) -> bool {
+        self.items.iter().all(Result::is_ok)
+    }
+}
+
+/// How much of a failed input an [`ItemError::input_summary`] repeats, see
+/// [`UrlShortenerService::with_summary_redaction`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SummaryRedaction {
+    /// Summaries repeat the input.
+    #[default]
+    Verbatim,
+
+    /// URLs are cut down to their scheme and host, so paths and query
+    /// strings never reach a response. Other inputs are repeated.
+    UrlsToHost,
+
+    /// Summaries are empty; the item's index is all that identifies it.
+    Hidden,
+}
+
+impl SummaryRedaction {
+    /// Summarizes an input of a bulk operation under this policy.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::SummaryRedaction;
+    ///
+    /// let redacted = SummaryRedaction::UrlsToHost.summarize("https://user@example.com/secret?q=1");
+    /// assert_eq!(redacted, "https://example.com");
+    /// assert_eq!(SummaryRedaction::UrlsToHost.summarize("promo"), "promo");
+    /// assert_eq!(SummaryRedaction::Hidden.summarize("promo"), "");
+    /// ```
+    pub fn summarize(&self, input: &str) -> String {
+        match self {
+            SummaryRedaction::Verbatim => input.to_string(),
+            SummaryRedaction::UrlsToHost => match input.split_once("://") {
+                Some((scheme, rest)) => {
+                    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+                    let host = authority.rsplit('@').next().unwrap_or_default();
+                    format!("{scheme}://{host}")
+                }
+                None => input.to_string()
+            },
+            SummaryRedaction::Hidden => String::new()
+        }
+    }
+}
+
+/// Variants of a [`Slug`] accepted when resolving a redirect.
+///
+/// Leniency is applied only at resolution time: slugs are always created and
+/// stored exactly as given, and the exact form always wins over a normalized
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LookupLeniency {
+    /// Retry without a single trailing `/` (`promo/` resolves to `promo`).
+    pub strip_trailing_slash: bool,
+
+    /// Compare slugs case-insensitively (`Promo` resolves to `promo`).
+    pub case_insensitive: bool,
+
+    /// Treat visually ambiguous characters as equal (`0` and `O`, `1`, `I`
+    /// and `l`).
+    pub map_ambiguous_chars: bool,
+}
+
+impl Default for LookupLeniency {
+    fn default() -> Self {
+        Self {
+            strip_trailing_slash: true,
+            case_insensitive: false,
+            map_ambiguous_chars: false
+        }
+    }
+}
+
+/// Branded presentation of slugs, such as `GO-x7k2` for the stored slug
+/// `x7k2`.
+///
+/// The policy affects only how slugs are shown and which requested forms
+/// resolve; slugs are always stored in their canonical form. The default
+/// policy displays slugs unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlugDisplayPolicy {
+    /// Text put in front of every displayed slug.
+    pub prefix: String,
+
+    /// Text between the prefix and the slug. Unused without a prefix.
+    pub separator: String,
+
+    /// Display slugs in upper case. Displayed forms of lowercase slugs resolve
+    /// back directly; mixed-case slugs additionally need case-insensitive
+    /// [`LookupLeniency`].
+    pub uppercase_display: bool,
+}
+
+impl SlugDisplayPolicy {
+    /// Returns the displayed form of a canonical slug.
+    pub fn display(&self, slug: &Slug) -> String {
+        let mut displayed = String::new();
+        if !self.prefix.is_empty() {
+            displayed.push_str(&self.prefix);
+            displayed.push_str(&self.separator);
+        }
+
+        if self.uppercase_display {
+            displayed.push_str(&slug.0.to_uppercase());
+        } else {
+            displayed.push_str(&slug.0);
+        }
+
+        displayed
+    }
+
+    /// Removes the prefix and separator from a requested slug, returning
+    /// `None` if it is not in displayed form.
+    fn strip_display<'a>(&self, requested: &'a str) -> Option<&'a str> {
+        if self.prefix.is_empty() {
+            return self.uppercase_display.then_some(requested);
+        }
+
+        let head_len = self.prefix.len() + self.separator.len();
+        let (head, rest) = (requested.get(..head_len)?, &requested[head_len..]);
+        let expected = format!("{}{}", self.prefix, self.separator);
+        let matches = if self.uppercase_display {
+            head.eq_ignore_ascii_case(&expected)
+        } else {
+            head == expected
+        };
+
+        matches.then_some(rest)
+    }
+}
+
+/// Features supported by a configured [`UrlShortenerService`], as returned by
+/// [`UrlShortenerService::capabilities`]. Every `with_*` setting is reflected
+/// except the [`Clock`] and [`SlugGenerator`], which can't describe
+/// themselves.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Capabilities {
+    /// Slug variants accepted when resolving redirects.
+    pub lookup_leniency: LookupLeniency,
+
+    /// How slugs are presented to users.
+    pub slug_display: SlugDisplayPolicy,
+
+    /// Number of links kept on the leaderboard, `0` if it is disabled.
+    pub leaderboard_size: usize,
+
+    /// Default handling of redirects to links marked dead.
+    pub dead_link_policy: DeadLinkPolicy,
+
+    /// Number of generated slugs tried before giving up with
+    /// [`ShortenerError::SlugGenerationFailed`].
+    pub slug_generation_attempts: usize,
+
+    /// Number of events after which a link's aggregate is snapshotted,
+    /// `None` if snapshots are disabled.
+    pub snapshot_interval: Option<usize>,
+
+    /// The deferred projection queue, `None` if projections are all
+    /// synchronous.
+    pub projection_queue: Option<ProjectionQueueConfig>,
+
+    /// Number of commands after which debug builds check invariants, `None`
+    /// if the check is disabled.
+    pub invariant_check_interval: Option<u64>,
+
+    /// Maximum length of a custom slug.
+    pub max_slug_len: usize,
+
+    /// Whether URLs are validated with the `url` crate (the `strict-url`
+    /// feature).
+    pub strict_url: bool,
+
+    /// Whether the event log can be exported and imported (the `serde`
+    /// feature).
+    pub serde: bool,
+
+    /// Whether custom commands and projections can be added (the
+    /// `extension` feature).
+    pub extension: bool,
+}
+
+/// Whether [`UrlShortenerService::repair`] only reports discrepancies or also
+/// fixes them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RepairMode {
+    /// Report discrepancies without changing anything.
+    Report,
+
+    /// Report discrepancies and rebuild the affected entries from events.
+    Fix,
+}
+
+/// How [`UrlShortenerService::backfill_timestamps`] dates events logged
+/// before timestamps existed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackfillStrategy {
+    /// Spread the events evenly from `from` to `to`, in log order.
+    SpreadEvenly {
+        /// Time of the first event.
+        from: SystemTime,
+
+        /// Time of the last event.
+        to: SystemTime,
+    },
+
+    /// Give each event the time of the next timestamped event in the log,
+    /// or of the previous one if none follows.
+    CopyFromNeighbor,
+
+    /// Give every event the same time.
+    ConstantAt(SystemTime),
+}
+
+/// Result of [`UrlShortenerService::backfill_timestamps`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BackfillReport {
+    /// Number of events whose timestamp was backfilled.
+    pub backfilled: usize,
+
+    /// Links with backfilled events, sorted. Each got a
+    /// [`EventType::TimestampsBackfilled`] event.
+    pub slugs: Vec<Slug>,
+}
+
+/// How far a copy of the event log has got with one link, see
+/// [`UrlShortenerService::version_vector`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SlugVersion {
+    /// Number of the link's events in the copy, its high-water mark.
+    pub events: usize,
+
+    /// Fingerprint of those events, telling diverged histories apart.
+    pub fingerprint: u64,
+}
+
+/// Per-link high-water marks of a copy of the event log, as returned by
+/// [`UrlShortenerService::version_vector`].
+pub type VersionVector = BTreeMap<Slug, SlugVersion>;
+
+/// The events of one link a copy is missing: positions `from..to` of the
+/// link's stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncRange {
+    /// The link.
+    pub slug: Slug,
+
+    /// Position of the first missing event, the copy's high-water mark.
+    pub from: usize,
+
+    /// Position after the last missing event.
+    pub to: usize,
+}
+
+/// The events a copy of the event log is missing, as computed by
+/// [`UrlShortenerService::diff_events`], one range per link, sorted by slug.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyncPlan {
+    /// The missing events of each link that has any.
+    pub links: Vec<SyncRange>,
+}
+
+impl SyncPlan {
+    /// Returns whether the copy is missing nothing.
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty()
+    }
+
+    /// Returns the number of missing events.
+    pub fn events(&self) -> usize {
+        self.links.iter().map(|range| range.to - range.from).sum()
+    }
+}
+
+/// Errors of [`UrlShortenerService::diff_events`] and
+/// [`UrlShortenerService::apply_sync_plan`]. Nothing is applied when one
+/// occurs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncError {
+    /// The copy's history of the link differs from the source's, or goes
+    /// beyond it.
+    Diverged(Slug),
+
+    /// The events don't continue the link's history where the plan says, or
+    /// aren't the ones the plan lists.
+    Discontinuous(Slug),
+
+    /// An event doesn't fit the link's history.
+    InvalidEvent {
+        /// The link.
+        slug: Slug,
+
+        /// Why the event doesn't fit.
+        error: TransitionError,
+    },
+}
+
+impl std::fmt::Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SyncError::Diverged(slug) => write!(f, "the history of link {slug} diverged"),
+            SyncError::Discontinuous(slug) => write!(f, "the events of link {slug} don't continue its history"),
+            SyncError::InvalidEvent { slug, error } => write!(f, "an event of link {slug} doesn't fit: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SyncError {}
+
+/// Discrepancies between the event store and the derived read models, found
+/// by [`UrlShortenerService::repair`]. Every list is sorted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RepairReport {
+    /// Slugs with [`Stats`] but no creation event.
+    pub orphan_stats: Vec<Slug>,
+
+    /// Slugs whose [`Stats`] differ from the ones replayed from events.
+    pub mismatched_stats: Vec<Slug>,
+
+    /// Slugs with a creation event but no [`Stats`].
+    pub missing_stats: Vec<Slug>,
+
+    /// Keys of lookup index entries not backed by any live link.
+    pub dangling_index_entries: Vec<String>,
+
+    /// Keys of lookup index entries that are absent or point elsewhere.
+    pub missing_index_entries: Vec<String>,
+
+    /// Slugs whose [`ServingHints`] differ from the ones replayed from events.
+    pub mismatched_serving_hints: Vec<Slug>,
+
+    /// Slugs whose dead-link state differs from the one replayed from events.
+    pub mismatched_dead_links: Vec<Slug>,
+
+    /// Slugs whose permanent flag differs from the one replayed from events.
+    pub mismatched_permanent_flags: Vec<Slug>,
+
+    /// Slugs whose expiry differs from the one replayed from events.
+    pub mismatched_expiries: Vec<Slug>,
+
+    /// Slugs whose redirect limit differs from the one replayed from events.
+    pub mismatched_redirect_limits: Vec<Slug>,
+
+    /// Slugs whose consumed one-time flag differs from the one replayed from
+    /// events.
+    pub mismatched_consumed_flags: Vec<Slug>,
+
+    /// URLs whose entry in the reverse lookup index differs from the one
+    /// replayed from events.
+    pub mismatched_url_index_entries: Vec<Url>,
+
+    /// Whether the [`Totals`] differ from the ones replayed from events.
+    pub mismatched_totals: bool,
+}
+
+impl RepairReport {
+    /// Returns `true` if no discrepancy was found.
+    pub fn is_consistent(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn sort(&mut self) {
+        self.orphan_stats.sort();
+        self.mismatched_stats.sort();
+        self.missing_stats.sort();
+        self.dangling_index_entries.sort();
+        self.missing_index_entries.sort();
+        self.mismatched_permanent_flags.sort();
+        self.mismatched_consumed_flags.sort();
+    }
+}
+
+/// A broken invariant between the event store and the read model, found by
+/// [`UrlShortenerService::assert_invariants`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvariantViolation {
+    /// The redirect counter of a link differs from the number of its
+    /// redirect events.
+    RedirectCountMismatch {
+        /// Affected link.
+        slug: Slug,
+
+        /// Number of redirect events in the event store.
+        events: u64,
+
+        /// Value of [`Stats::redirects`].
+        counter: u64,
+    },
+
+    /// The URL of a link differs from the latest one recorded for it.
+    UrlMismatch {
+        /// Affected link.
+        slug: Slug,
+
+        /// URL recorded by the latest creation or target update event.
+        events: Url,
+
+        /// URL of [`Stats::link`].
+        stored: Url,
+    },
+
+    /// A link was created but has no [`Stats`].
+    MissingStats {
+        /// Affected link.
+        slug: Slug,
+    },
+
+    /// [`Stats`] exist for a slug without any events, or for a deleted link.
+    OrphanStats {
+        /// Affected slug.
+        slug: Slug,
+    },
+}
+
+impl InvariantViolation {
+    /// Returns the slug the violation relates to.
+    pub fn slug(&self) -> &Slug {
+        match self {
+            InvariantViolation::RedirectCountMismatch { slug, .. } => slug,
+            InvariantViolation::UrlMismatch { slug, .. } => slug,
+            InvariantViolation::MissingStats { slug } => slug,
+            InvariantViolation::OrphanStats { slug } => slug,
+        }
+    }
+}
+
+/// A system link that [`UrlShortenerService::bootstrap`] makes sure exists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BootstrapLink {
+    /// Slug of the system link.
+    pub slug: Slug,
+
+    /// Destination of the system link.
+    pub url: Url,
+
+    /// Tags of the system link, replacing any it has.
+    pub tags: Vec<String>,
+
+    /// How the system link is protected.
+    pub flags: SeedFlags,
+}
+
+impl BootstrapLink {
+    /// A seed without tags or flags.
+    pub fn new(slug: impl Into<Slug>, url: impl Into<Url>) -> Self {
+        BootstrapLink { slug: slug.into(), url: url.into(), tags: Vec::new(), flags: SeedFlags::default() }
+    }
+
+    /// Sets the tags of the seed.
+    pub fn with_tags<I: IntoIterator<Item = S>, S: Into<String>>(mut self, tags: I) -> Self {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the flags of the seed.
+    pub fn with_flags(mut self, flags: SeedFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+}
+
+/// How [`UrlShortenerService::bootstrap`] protects a seeded link.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SeedFlags {
+    /// Keep the slug for the seed: if the link is deleted, creating a link
+    /// under the slug fails with [`ShortenerError::ProtectedLink`] until
+    /// the seed creates it again.
+    pub reserved: bool,
+
+    /// Make the link permanent, see
+    /// [`ExtendedCommandHandler::handle_make_permanent`](commands::ExtendedCommandHandler::handle_make_permanent).
+    /// Bootstrap never lifts permanence, so unsetting the flag later has no
+    /// effect.
+    pub permanent: bool,
+
+    /// Refuse to delete the link with [`ShortenerError::ProtectedLink`],
+    /// even when overriding permanence.
+    pub undeletable: bool,
+}
+
+/// The protection of a link recorded by [`UrlShortenerService::bootstrap`],
+/// see [`SeedFlags`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LinkProtection {
+    /// See [`SeedFlags::reserved`].
+    pub reserved: bool,
+
+    /// See [`SeedFlags::undeletable`].
+    pub undeletable: bool,
+}
+
+/// What [`UrlShortenerService::bootstrap`] does with a seeded link whose URL
+/// no longer matches its seed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum BootstrapDrift {
+    /// List it in [`BootstrapReport::drifted`] and leave it pointing where
+    /// it does.
+    #[default]
+    Report,
+
+    /// Point it back to the seeded URL and list it in
+    /// [`BootstrapReport::corrected`].
+    Correct,
+}
+
+/// Summary of a [`UrlShortenerService::bootstrap`] run, in seed order.
+#[derive(Debug, Default, PartialEq)]
+pub struct BootstrapReport {
+    /// Seeds that were missing and have been created.
+    pub created: Vec<Slug>,
+
+    /// Seeds that already existed with the seeded URL, tags and flags.
+    pub unchanged: Vec<Slug>,
+
+    /// Seeds that exist with a different URL, left as they are under
+    /// [`BootstrapDrift::Report`].
+    pub drifted: Vec<Slug>,
+
+    /// Seeds whose URL drifted and was pointed back under
+    /// [`BootstrapDrift::Correct`].
+    pub corrected: Vec<Slug>,
+
+    /// Existing seeds whose tags or flags were brought in line with the
+    /// seed, including drifted ones.
+    pub updated: Vec<Slug>,
+
+    /// Seeds that could not be created or corrected, e.g. because the link
+    /// is permanent.
+    pub failed: Vec<(Slug, ShortenerError)>,
+}
+
+/// Options of [`UrlShortenerService::migrate_from_map`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationOptions {
+    /// Migrate links whose URL fails validation instead of skipping them.
+    /// Such links keep working but are listed in
+    /// [`MigrationReport::grandfathered`], and their history records an
+    /// [`EventType::UrlGrandfathered`] event.
+    pub lenient_urls: bool,
+
+    /// Timestamp of the synthesized events, typically when the links were
+    /// created in the old system. Defaults to the service's [`Clock`].
+    pub created_at: Option<SystemTime>,
+}
+
+/// Why [`UrlShortenerService::migrate_from_map`] skipped an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationSkipReason {
+    /// The URL fails validation and lenient mode is off.
+    InvalidUrl(InvalidUrlReason),
+
+    /// An earlier entry already used the slug.
+    DuplicateSlug,
+
+    /// The slug isn't usable as a path segment, see
+    /// [`ShortenerError::InvalidSlug`].
+    InvalidSlug,
+}
+
+/// Summary of a [`UrlShortenerService::migrate_from_map`] run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MigrationReport {
+    /// Slugs that were migrated, in input order.
+    pub migrated: Vec<Slug>,
+
+    /// Migrated slugs whose URL fails validation, in input order.
+    pub grandfathered: Vec<Slug>,
+
+    /// Entries that were not migrated, in input order.
+    pub skipped: Vec<(Slug, MigrationSkipReason)>,
+}
+
+/// Errors of [`UrlShortenerService::migrate_from_map`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationError {
+    /// Migration only seeds a service that holds no events yet.
+    ServiceNotEmpty,
+}
+
+/// Why an event can't follow its link's history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionError {
+    /// An event other than a creation for a link that was never created,
+    /// e.g. an update before the create.
+    NotCreated,
+
+    /// A creation of a link that already exists and wasn't deleted.
+    AlreadyCreated,
+
+    /// An event other than a creation for a link that was deleted, e.g. a
+    /// redirect after the delete.
+    Deleted,
+}
+
+impl std::fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionError::NotCreated => write!(f, "the link was never created"),
+            TransitionError::AlreadyCreated => write!(f, "the link already exists"),
+            TransitionError::Deleted => write!(f, "the link was deleted"),
+        }
+    }
+}
+
+/// An event of a stream that doesn't fit its link's history, as reported by
+/// [`UrlShortenerService::validate_events`].
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidTransition {
+    /// The link the event belongs to.
+    pub slug: Slug,
+
+    /// Position of the event in the stream, starting at 1 like
+    /// [`EventView::sequence`].
+    pub sequence: u64,
+
+    /// What the event recorded.
+    pub event_type: EventType,
+
+    /// Why it doesn't fit.
+    pub error: TransitionError,
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for InvalidTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "event {} of link {} doesn't fit its history: {}", self.sequence, self.slug, self.error)
+    }
+}
+
+/// How [`UrlShortenerService::import_events_with`] treats events that don't
+/// fit their link's history.
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// The import fails at the first such event.
+    #[default]
+    Strict,
+
+    /// Such events are skipped and reported, the rest is imported.
+    Lenient,
+}
+
+/// Errors of [`UrlShortenerService::import_events`].
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum ImportError {
+    /// Reading the input failed.
+    Io(std::io::Error),
+
+    /// A line is not a valid JSON event.
+    Parse {
+        /// One-based number of the offending line.
+        line: usize,
+
+        /// Description of the parse error.
+        message: String,
+    },
+
+    /// An event doesn't fit its link's history, e.g. a redirect before the
+    /// link was created.
+    InvalidEvent {
+        /// One-based number of the offending line.
+        line: usize,
+
+        /// The offending event and why it doesn't fit.
+        transition: InvalidTransition,
+    },
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::Io(error) => write!(f, "reading the event log failed: {error}"),
+            ImportError::Parse { line, message } => write!(f, "line {line} is not a valid event: {message}"),
+            ImportError::InvalidEvent { line, transition } => {
+                write!(f, "the event on line {line} doesn't fit its link's history: {}", transition.error)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImportError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+/// Errors of [`UrlShortenerService::load_from_path`].
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum LoadError {
+    /// Reading the file failed.
+    Io(std::io::Error),
+
+    /// The file was written in a format version this build can't read:
+    /// by a newer version, or older than any it migrates.
+    UnsupportedVersion(u32),
+
+    /// The file is not a state file, or is truncated or corrupted.
+    Malformed(String),
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(error) => write!(f, "reading the state file failed: {error}"),
+            LoadError::UnsupportedVersion(version) => write!(f, "state file format version {version} is not supported"),
+            LoadError::Malformed(message) => write!(f, "the state file is malformed: {message}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+/// First line of a file written by [`UrlShortenerService::save_to_path`],
+/// followed by a space and the format version.
+#[cfg(feature = "serde")]
+const STATE_FILE_HEADER: &str = "url-shortener-state";
+
+/// Format version written by [`UrlShortenerService::save_to_path`]; bump it
+/// whenever [`SavedState`] changes.
+#[cfg(feature = "serde")]
+const STATE_FILE_VERSION: u32 = 2;
+
+/// Oldest format version [`UrlShortenerService::load_from_path`] migrates.
+/// Version 1 lacks `redirect_limits` and `consumed_slugs`.
+#[cfg(feature = "serde")]
+const OLDEST_STATE_FILE_VERSION: u32 = 1;
+
+/// The event log and the read models that can't be cheaply derived from
+/// [`Stats`], as persisted by [`UrlShortenerService::save_to_path`].
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedState {
+    events: Vec<Event>,
+    stats: BTreeMap<Slug, Stats>,
+    serving_hints: HashMap<Slug, ServingHints>,
+    link_health: HashMap<Slug, LinkHealth>,
+    permanent_slugs: HashSet<Slug>,
+    expiries: HashMap<Slug, SystemTime>,
+    #[serde(default)]
+    redirect_limits: HashMap<Slug, u64>,
+    #[serde(default)]
+    consumed_slugs: HashSet<Slug>
+}
+
+/// Dead-link state of a short link as kept by the read model.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct LinkHealth {
+    /// Evidence of the destination being dead, `None` while it is alive.
+    dead: Option<String>,
+
+    /// Per-link override of the service's [`DeadLinkPolicy`].
+    policy: Option<DeadLinkPolicy>,
+}
+
+/// How many generated slugs are tried by default before giving up with
+/// [`ShortenerError::SlugGenerationFailed`].
+const DEFAULT_SLUG_GENERATION_ATTEMPTS: usize = 10;
+
+/// Number of events per link after which its aggregate is snapshotted by
+/// default.
+const DEFAULT_SNAPSHOT_INTERVAL: usize = 100;
+
+/// Number of redirect timestamps kept per link by default, see
+/// [`UrlShortenerService::with_recent_clicks`].
+const DEFAULT_RECENT_CLICKS: usize = 128;
+
+/// A read model of custom events, see
+/// [`UrlShortenerService::with_custom_projection`].
+#[cfg(feature = "extension")]
+type CustomProjection = Box<dyn FnMut(&Slug, &str, &str)>;
+
+/// Called with a link and its expiry, see
+/// [`UrlShortenerService::with_expiry_notifier`].
+type ExpiryNotice = Box<dyn FnMut(&ShortLink, SystemTime)>;
+
+/// An expiry notice sent once a link expires within `lead`.
+struct ExpiryNotifier {
+    lead: Duration,
+    notify: ExpiryNotice
+}
+
+/// CQRS and Event Sourcing-based service implementation
+///
+/// ## Ordering
+///
+/// Every event goes into one append-only log the moment its command raises
+/// it, and commands take `&mut self`, so they run one at a time. Hence:
+///
+/// - the global sequence follows command completion order: the events of a
+///   command are contiguous and come after those of every command that
+///   completed before it;
+/// - a link's events are totally ordered, in the order its commands ran,
+///   and keep that order in [`UrlShortenerService::get_events`],
+///   [`UrlShortenerService::events_since`] and every export.
+///
+/// Deferred projections (see [`UrlShortenerService::with_projection_queue`])
+/// only delay read models; they never reorder or buffer the log.
+///
+/// ## Examples
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use url_shortener::commands::CommandHandler;
+/// use url_shortener::queries::QueryHandler;
+/// use url_shortener::{Base62Generator, FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+///
+/// // A fixed clock and a seeded generator make the service deterministic.
+/// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+/// let mut service = UrlShortenerService::new()
+///     .with_clock(FixedClock(now))
+///     .with_slug_generator(Base62Generator::new(7).with_seed(42));
+///
+/// let google_url = || Url::from("https://google.com");
+/// let google = service.handle_create_short_link(google_url(), Some(Slug::from("goog")))?;
+/// let generated = service.handle_create_short_link(google_url(), None)?;
+/// assert_eq!(generated.slug, Slug::from("PxsioKx"));
+///
+/// let duplicate = service.handle_create_short_link(google_url(), Some(Slug::from("goog")));
+/// assert_eq!(duplicate, Err(ShortenerError::SlugAlreadyInUse));
+/// let invalid = service.handle_create_short_link(Url::from("invalid-url"), None);
+/// assert_eq!(invalid, Err(ShortenerError::InvalidUrl));
+///
+/// // Every redirect is recorded as an event and counted.
+/// service.handle_redirect(google.slug.clone())?;
+/// service.handle_redirect(google.slug.clone())?;
+/// let stats = service.get_stats(google.slug.clone())?;
+/// assert_eq!((stats.redirects, stats.created_at), (2, now));
+/// assert_eq!(service.handle_redirect(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+/// assert_eq!(service.get_stats(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+///
+/// // Stats are derived from the events, so they can be rebuilt from them.
+/// assert_eq!(service.get_events(&google.slug)?.len(), 3);
+/// service.rebuild_read_model();
+/// assert_eq!(service.get_stats(google.slug)?, stats);
+/// # Ok::<(), ShortenerError>(())
+/// ```
+pub struct UrlShortenerService {
+    log: Vec<Event>,
+    index: ResolveIndex,
+    stats: BTreeMap<Slug, Stats>,
+    leniency: LookupLeniency,
+    folded_slugs: HashMap<String, Slug>,
+    slugs_by_url: HashMap<String, BTreeSet<Slug>>,
+    creation_order: CreationOrder,
+    cursor_key: u64,
+    seeded: SeededLinks,
+    bootstrap_drift: BootstrapDrift,
+    stats_grants: StatsGrants,
+    recent_clicks: RecentClicks,
+    display_policy: SlugDisplayPolicy,
+    serving_hints: HashMap<Slug, ServingHints>,
+    link_health: HashMap<Slug, LinkHealth>,
+    dead_link_policy: DeadLinkPolicy,
+    summary_redaction: SummaryRedaction,
+    permanent_slugs: HashSet<Slug>,
+    slug_generator: Box<dyn SlugGenerator>,
+    slug_generation_attempts: usize,
+    word_filter: Option<WordFilter>,
+    clock: Box<dyn Clock>,
+    snapshot_interval: Option<usize>,
+    expiries: HashMap<Slug, SystemTime>,
+    expiry_order: ExpiryOrder,
+    expiry_notifiers: Vec<ExpiryNotifier>,
+    redirect_limits: HashMap<Slug, u64>,
+    consumed_slugs: HashSet<Slug>,
+    outcomes: HashMap<Slug, OutcomeStats>,
+    global_outcomes: OutcomeStats,
+    leaderboard_size: usize,
+    leaderboard: Leaderboard,
+    totals: Totals,
+    projection_queue: Option<ProjectionQueue>,
+    invariant_check_interval: Option<u64>,
+    commands_handled: u64,
+    #[cfg(feature = "extension")]
+    custom_projections: Vec<CustomProjection>
+}
+
+impl UrlShortenerService {
+    /// Creates a new instance of the service
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Totals, UrlShortenerService};
+    ///
+    /// let service = UrlShortenerService::new();
+    ///
+    /// assert_eq!(service.totals(), Totals { links: 0, redirects: 0 });
+    /// assert_eq!(service.get_stats(Slug::from("docs")), Err(ShortenerError::SlugNotFound));
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            log: Vec::new(),
+            index: ResolveIndex::default(),
+            stats: BTreeMap::new(),
+            leniency: LookupLeniency::default(),
+            folded_slugs: HashMap::new(),
+            slugs_by_url: HashMap::new(),
+            creation_order: CreationOrder::default(),
+            cursor_key: RandomState::new().build_hasher().finish(),
+            seeded: SeededLinks::default(),
+            bootstrap_drift: BootstrapDrift::default(),
+            stats_grants: StatsGrants::default(),
+            recent_clicks: RecentClicks::default(),
+            display_policy: SlugDisplayPolicy::default(),
+            serving_hints: HashMap::new(),
+            link_health: HashMap::new(),
+            dead_link_policy: DeadLinkPolicy::default(),
+            summary_redaction: SummaryRedaction::default(),
+            permanent_slugs: HashSet::new(),
+            slug_generator: Box::new(DefaultSlugGenerator),
+            slug_generation_attempts: DEFAULT_SLUG_GENERATION_ATTEMPTS,
+            word_filter: None,
+            clock: Box::new(SystemClock),
+            snapshot_interval: Some(DEFAULT_SNAPSHOT_INTERVAL),
+            expiries: HashMap::new(),
+            expiry_order: ExpiryOrder::default(),
+            expiry_notifiers: Vec::new(),
+            redirect_limits: HashMap::new(),
+            consumed_slugs: HashSet::new(),
+            outcomes: HashMap::new(),
+            global_outcomes: OutcomeStats::default(),
+            leaderboard_size: 0,
+            leaderboard: Leaderboard::default(),
+            totals: Totals::default(),
+            projection_queue: None,
+            invariant_check_interval: None,
+            commands_handled: 0,
+            #[cfg(feature = "extension")]
+            custom_projections: Vec::new()
+        }
+    }
+
+    /// Replaces the [`LookupLeniency`] used to resolve redirects.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{LookupLeniency, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let leniency = LookupLeniency { case_insensitive: true, ..LookupLeniency::default() };
+    /// let mut service = UrlShortenerService::new().with_lookup_leniency(leniency);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// assert_eq!(service.handle_redirect(Slug::from("DOCS"))?.slug, Slug::from("docs"));
+    /// assert_eq!(service.handle_redirect(Slug::from("DOCZ")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_lookup_leniency(mut self, leniency: LookupLeniency) -> Self {
+        self.leniency = leniency;
+        self.folded_slugs = Self::build_folded_index(&self.stats, &self.leniency);
+        self
+    }
+
+    /// Replaces the [`SlugDisplayPolicy`]. Stored slugs are unaffected, so
+    /// changing the policy never breaks existing links.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, SlugDisplayPolicy, Url, UrlShortenerService};
+    ///
+    /// let policy =
+    ///     SlugDisplayPolicy { prefix: "acme".to_string(), separator: "-".to_string(), uppercase_display: true };
+    /// let mut service = UrlShortenerService::new().with_slug_display_policy(policy);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// assert_eq!(service.handle_redirect(Slug::from("acme-DOCS"))?.slug, Slug::from("docs"));
+    /// assert_eq!(service.handle_redirect(Slug::from("other-DOCS")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_slug_display_policy(mut self, policy: SlugDisplayPolicy) -> Self {
+        self.display_policy = policy;
+        self
+    }
+
+    /// Returns the branded form of a slug according to the service's
+    /// [`SlugDisplayPolicy`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::{Slug, SlugDisplayPolicy, UrlShortenerService};
+    ///
+    /// let policy =
+    ///     SlugDisplayPolicy { prefix: "acme".to_string(), separator: "/".to_string(), uppercase_display: false };
+    /// let service = UrlShortenerService::new().with_slug_display_policy(policy);
+    ///
+    /// assert_eq!(service.display_slug(&Slug::from("docs")), "acme/docs");
+    /// assert_eq!(UrlShortenerService::new().display_slug(&Slug::from("docs")), "docs");
+    /// ```
+    pub fn display_slug(&self, slug: &Slug) -> String {
+        self.display_policy.display(slug)
+    }
+
+    /// Maintains a leaderboard of the `size` most redirected links, see
+    /// [`UrlShortenerService::leaderboard`]. Memory use is bounded by `size`.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_leaderboard(2);
+    /// for (slug, redirects) in [("a", 1), ("b", 3), ("c", 2)] {
+    ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+    ///     for _ in 0..redirects {
+    ///         service.handle_redirect(Slug::from(slug))?;
+    ///     }
+    /// }
+    ///
+    /// let board = service.leaderboard().expect("the leaderboard is enabled");
+    /// assert_eq!(board.entries, [(Slug::from("b"), 3), (Slug::from("c"), 2)]);
+    /// assert!(UrlShortenerService::new().leaderboard().is_none());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_leaderboard(mut self, size: usize) -> Self {
+        self.leaderboard_size = size;
+        self.reconcile_leaderboards();
+        self
+    }
+
+    /// Keeps the timestamps of the latest `capacity` redirects of each link,
+    /// from which [`UrlShortenerService::get_click_interval_stats`] measures
+    /// recent traffic without replaying the link's history. 128 by default;
+    /// 0 keeps none.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ClickIntervals, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_recent_clicks(1);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let recent = service.get_click_interval_stats(&Slug::from("docs"), false)?;
+    /// assert_eq!(recent, ClickIntervals::InsufficientData { clicks: 1 });
+    /// assert!(matches!(service.get_click_interval_stats(&Slug::from("docs"), true)?, ClickIntervals::Measured(_)));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_recent_clicks(mut self, capacity: usize) -> Self {
+        self.recent_clicks.set_capacity(capacity);
+        self
+    }
+
+    /// Calls `notifier` with a link and its expiry when
+    /// [`UrlShortenerService::sweep_expiry_notices`] finds the link expiring
+    /// within `lead`, so owners can renew it. Each link is announced at most
+    /// once per lead time: the notice is recorded as an
+    /// [`EventType::ExpiryNoticeSent`] event, so it is remembered across
+    /// export and import, and only setting a new expiry re-arms it. Several
+    /// notifiers with different lead times may be configured.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::ExtendedCommandHandler;
+    /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    /// let week = Duration::from_secs(7 * 24 * 3_600);
+    /// let notices = Rc::new(RefCell::new(Vec::new()));
+    /// let sent = Rc::clone(&notices);
+    /// let mut service = UrlShortenerService::new()
+    ///     .with_clock(FixedClock(now))
+    ///     .with_expiry_notifier(week, move |link, at| sent.borrow_mut().push((link.slug.clone(), at)));
+    /// service.handle_create_with_ttl(Url::from("https://example.com"), Some(Slug::from("sale")), 3_600)?;
+    ///
+    /// assert_eq!(service.sweep_expiry_notices(), 1);
+    /// assert_eq!(service.sweep_expiry_notices(), 0);
+    /// assert_eq!(*notices.borrow(), [(Slug::from("sale"), now + Duration::from_secs(3_600))]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_expiry_notifier(
+        mut self,
+        lead: Duration,
+        notifier: impl FnMut(&ShortLink, SystemTime) + 'static,
+    ) -> Self {
+        self.expiry_notifiers.push(ExpiryNotifier { lead, notify: Box::new(notifier) });
+        self
+    }
+
+    /// Defers the expensive projections (the leaderboard) to
+    /// [`UrlShortenerService::pump_projections`] instead of updating them
+    /// while handling each command. Stats, the lookup index, serving hints and
+    /// dead-link state stay synchronous, so redirects and queries on them are
+    /// never stale. At most `capacity` events wait in the queue.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{QueueOverflow, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service =
+    ///     UrlShortenerService::new().with_leaderboard(3).with_projection_queue(2, QueueOverflow::DropWithCounter);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// // The third event didn't fit in the queue.
+    /// let status = service.projection_queue_status().expect("the queue is configured");
+    /// assert_eq!((status.pending, status.dropped), (2, 1));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_projection_queue(mut self, capacity: usize, overflow: QueueOverflow) -> Self {
+        self.projection_queue = Some(ProjectionQueue {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            overflow,
+            dropped: 0
+        });
+        self
+    }
+
+    /// Applies up to `max_events` queued events to the deferred projections,
+    /// oldest first, and returns how many were applied.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{QueueOverflow, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service =
+    ///     UrlShortenerService::new().with_leaderboard(3).with_projection_queue(16, QueueOverflow::AutoPump);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// assert!(service.leaderboard().is_some_and(|board| board.entries.is_empty()));
+    ///
+    /// assert_eq!(service.pump_projections(10), 2);
+    /// assert_eq!(service.leaderboard().map(|board| board.entries), Some(vec![(Slug::from("docs"), 1)]));
+    /// assert_eq!(service.pump_projections(10), 0);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn pump_projections(&mut self, max_events: usize) -> usize {
+        let mut applied = 0;
+        while applied < max_events {
+            let Some(event) = self.projection_queue.as_mut().and_then(|queue| queue.events.pop_front()) else {
+                break;
+            };
+            self.project_deferred(&event);
+            applied += 1;
+        }
+
+        applied
+    }
+
+    /// Returns the state of the projection queue, or `None` if projections
+    /// are all synchronous.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{QueueOverflow, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_projection_queue(8, QueueOverflow::AutoPump);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// let status = service.projection_queue_status().expect("the queue is configured");
+    /// assert_eq!((status.pending, status.capacity, status.dropped), (1, 8, 0));
+    /// assert!(UrlShortenerService::new().projection_queue_status().is_none());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn projection_queue_status(&self) -> Option<ProjectionQueueStatus> {
+        self.projection_queue.as_ref().map(|queue| ProjectionQueueStatus {
+            pending: queue.events.len(),
+            capacity: queue.capacity,
+            dropped: queue.dropped
+        })
+    }
+
+    /// Replaces the [`SlugGenerator`] used for links created without a slug.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{Base62Generator, ShortenerError, Url, UrlShortenerService};
+    ///
+    /// let seeded = || UrlShortenerService::new().with_slug_generator(Base62Generator::new(5).with_seed(7));
+    /// let (mut first, mut second) = (seeded(), seeded());
+    ///
+    /// let link = first.handle_create_short_link(Url::from("https://example.com"), None)?;
+    /// assert_eq!(link.slug.0.len(), 5);
+    /// assert_eq!(second.handle_create_short_link(Url::from("https://example.com"), None)?, link);
+    /// assert_eq!(first.handle_create_short_link(Url::from(""), None), Err(ShortenerError::InvalidUrl));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_slug_generator(mut self, generator: impl SlugGenerator + 'static) -> Self {
+        self.slug_generator = Box::new(generator);
+        self
+    }
+
+    /// Discards generated slugs that spell a word of `list`, either as a
+    /// whole or in any part at least `min_len` characters long, ignoring
+    /// case, and tries the next candidate instead. Discarded candidates count
+    /// towards [`UrlShortenerService::with_slug_generation_attempts`] but
+    /// never reach the event store. Slugs given by the caller are not
+    /// filtered.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::SystemTime;
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{DefaultWordList, ShortenerError, Slug, SlugGenerator, Url, UrlShortenerService};
+    ///
+    /// /// Proposes the given slugs in turn.
+    /// struct Rigged(Vec<&'static str>);
+    ///
+    /// impl SlugGenerator for Rigged {
+    ///     fn generate(&mut self, _now: SystemTime) -> Slug {
+    ///         Slug::from(self.0.remove(0))
+    ///     }
+    /// }
+    ///
+    /// let mut service = UrlShortenerService::new()
+    ///     .with_slug_generator(Rigged(vec!["xSHITx", "k7Qp2"]))
+    ///     .with_word_filter(DefaultWordList, 4);
+    /// assert_eq!(service.handle_create_short_link(Url::from("https://example.com"), None)?.slug, Slug::from("k7Qp2"));
+    ///
+    /// let custom = service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("shit")))?;
+    /// assert_eq!(custom.slug, Slug::from("shit"));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_word_filter(mut self, list: impl WordList + 'static, min_len: usize) -> Self {
+        self.word_filter = Some(WordFilter { list: Box::new(list), min_len });
+        self
+    }
+
+    /// Sets how many generated slugs are tried before creating a link without
+    /// a slug fails with [`ShortenerError::SlugGenerationFailed`]. At least
+    /// one attempt is always made.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::SystemTime;
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, SlugGenerator, Url, UrlShortenerService};
+    ///
+    /// /// Proposes the same slug every time.
+    /// struct Stuck;
+    ///
+    /// impl SlugGenerator for Stuck {
+    ///     fn generate(&mut self, _now: SystemTime) -> Slug {
+    ///         Slug::from("stuck")
+    ///     }
+    /// }
+    ///
+    /// let mut service = UrlShortenerService::new().with_slug_generator(Stuck).with_slug_generation_attempts(3);
+    ///
+    /// assert_eq!(service.handle_create_short_link(Url::from("https://example.com"), None)?.slug, Slug::from("stuck"));
+    /// let retried = service.handle_create_short_link(Url::from("https://example.com"), None);
+    /// assert_eq!(retried, Err(ShortenerError::SlugGenerationFailed));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_slug_generation_attempts(mut self, attempts: usize) -> Self {
+        self.slug_generation_attempts = attempts.max(1);
+        self
+    }
+
+    /// Snapshots a link's aggregate every `interval` events, so commands
+    /// replay at most that many events instead of the link's whole history.
+    /// `0` disables snapshots.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_snapshot_interval(10);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("hot")))?;
+    /// for _ in 0..25 {
+    ///     service.handle_redirect(Slug::from("hot"))?;
+    /// }
+    ///
+    /// assert_eq!(service.get_stats(Slug::from("hot"))?.redirects, 25);
+    /// assert_eq!(service.capabilities().snapshot_interval, Some(10));
+    /// assert_eq!(UrlShortenerService::new().with_snapshot_interval(0).capabilities().snapshot_interval, None);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_snapshot_interval(mut self, interval: usize) -> Self {
+        self.snapshot_interval = (interval > 0).then_some(interval);
+        self.index.clear_snapshots();
+        self
+    }
+
+    /// Replaces the [`Clock`] used for every time-dependent decision, such as
+    /// expiry and generated slugs. A clock chosen at runtime can be passed
+    /// as a `Box<dyn Clock>`.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+    ///
+    /// service.handle_create_with_ttl(Url::from("https://example.com"), Some(Slug::from("brief")), 0)?;
+    /// assert_eq!(service.get_stats(Slug::from("brief"))?.created_at, now);
+    /// assert_eq!(service.handle_redirect(Slug::from("brief")), Err(ShortenerError::LinkExpired));
+    ///
+    /// let clock: Box<dyn url_shortener::Clock> = Box::new(FixedClock(now));
+    /// let mut service = UrlShortenerService::new().with_clock(clock);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// assert_eq!(service.get_stats(Slug::from("docs"))?.created_at, now);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Sets the [`DeadLinkPolicy`] applied to links marked dead that have no
+    /// policy of their own.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{DeadLinkPolicy, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_dead_link_policy(DeadLinkPolicy::ReturnError);
+    /// for slug in ["gone", "kept"] {
+    ///     service.handle_create_short_link(Url::from("https://example.com/gone"), Some(Slug::from(slug)))?;
+    ///     service.handle_mark_dead(Slug::from(slug), "404".to_string())?;
+    /// }
+    /// service.handle_set_dead_link_policy(Slug::from("kept"), Some(DeadLinkPolicy::ServeAnyway))?;
+    ///
+    /// assert_eq!(service.handle_redirect(Slug::from("gone")), Err(ShortenerError::LinkDead));
+    /// assert!(service.handle_redirect(Slug::from("kept")).is_ok());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_dead_link_policy(mut self, policy: DeadLinkPolicy) -> Self {
+        self.dead_link_policy = policy;
+        self
+    }
+
+    /// Sets how much of a failed input the [`ItemError`]s of bulk
+    /// operations repeat. Summaries are verbatim by default.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::{Slug, SummaryRedaction, UrlShortenerService};
+    ///
+    /// let service = UrlShortenerService::new().with_summary_redaction(SummaryRedaction::UrlsToHost);
+    /// let bulk = service.get_stats_bulk(&[Slug::from("https://sho.rt/promo"), Slug::from("missing")]);
+    ///
+    /// let summaries: Vec<_> = bulk.failed().map(|item| item.input_summary.as_str()).collect();
+    /// assert_eq!(summaries, ["https://sho.rt", "missing"]);
+    /// ```
+    pub fn with_summary_redaction(mut self, redaction: SummaryRedaction) -> Self {
+        self.summary_redaction = redaction;
+        self
+    }
+
+    /// Sets the key page cursors are signed with. By default every service
+    /// picks a random key, so its cursors are refused by any other instance,
+    /// including one loaded from its saved state; instances serving the same
+    /// clients should share a key.
+    ///
+    /// The signature is a keyed SipHash, enough to detect cursors edited by
+    /// clients but not a cryptographic MAC.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::ExtendedQueryHandler;
+    /// use url_shortener::{CursorError, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_cursor_key(7);
+    /// for slug in ["a", "b"] {
+    ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+    /// }
+    /// let next = service.list_links(None, 1).unwrap().next.unwrap();
+    ///
+    /// let mut restarted = UrlShortenerService::new().with_cursor_key(7);
+    /// for slug in ["a", "b"] {
+    ///     restarted.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+    /// }
+    /// assert_eq!(restarted.list_links(Some(&next), 1).unwrap().links[0].link.slug, Slug::from("b"));
+    /// assert_eq!(UrlShortenerService::new().list_links(Some(&next), 1), Err(CursorError::Tampered));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_cursor_key(mut self, key: u64) -> Self {
+        self.cursor_key = key;
+        self
+    }
+
+    /// Sets what [`UrlShortenerService::bootstrap`] does with seeded links
+    /// whose URL drifted from their seed, [`BootstrapDrift::Report`] by
+    /// default. Correcting a permanent link fails and is reported in
+    /// [`BootstrapReport::failed`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::ExtendedCommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{BootstrapDrift, BootstrapLink, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_bootstrap_drift(BootstrapDrift::Correct);
+    /// let seed = BootstrapLink::new("docs", "https://docs.example.com");
+    /// service.bootstrap(&[seed.clone()]);
+    /// service.handle_update_target(Slug::from("docs"), Url::from("https://wiki.example.com"))?;
+    ///
+    /// assert_eq!(service.bootstrap(&[seed]).corrected, [Slug::from("docs")]);
+    /// assert_eq!(service.get_stats(Slug::from("docs"))?.link.url, Url::from("https://docs.example.com"));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_bootstrap_drift(mut self, drift: BootstrapDrift) -> Self {
+        self.bootstrap_drift = drift;
+        self
+    }
+
+    /// Makes debug builds run [`UrlShortenerService::assert_invariants`] after
+    /// every `interval`-th command and panic on any violation. Release builds
+    /// ignore this setting.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// // Every command is followed by a check, which panics on a violation.
+    /// let mut service = UrlShortenerService::new().with_invariant_checks(1);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// assert_eq!(service.handle_redirect(Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+    /// assert_eq!(service.capabilities().invariant_check_interval, Some(1));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn with_invariant_checks(mut self, interval: u64) -> Self {
+        self.invariant_check_interval = Some(interval.max(1));
+        self
+    }
+
+    /// Calls `projection` with the slug, name and payload of every
+    /// [`EventType::Custom`] event, first for the ones already recorded and
+    /// then for each new one as it is published, so that commands defined
+    /// outside this crate can keep read models of their own.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::cell::RefCell;
+    /// use std::rc::Rc;
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let notes = Rc::new(RefCell::new(Vec::new()));
+    /// let seen = Rc::clone(&notes);
+    /// let mut service = UrlShortenerService::new()
+    ///     .with_custom_projection(move |slug, name, payload| {
+    ///         seen.borrow_mut().push(format!("{slug} {name}: {payload}"))
+    ///     });
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// service.emit_custom(Slug::from("docs"), "note".to_string(), "reviewed".to_string())?;
+    /// assert!(service.emit_custom(Slug::from("missing"), "note".to_string(), String::new()).is_err());
+    /// assert_eq!(*notes.borrow(), ["docs note: reviewed"]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    #[cfg(feature = "extension")]
+    pub fn with_custom_projection(mut self, mut projection: impl FnMut(&Slug, &str, &str) + 'static) -> Self {
+        for event in &self.log {
+            if let EventType::Custom { name, payload } = &event.event_type {
+                projection(&event.slug, name, payload);
+            }
+        }
+
+        self.custom_projections.push(Box::new(projection));
+        self
+    }
+
+    /// Records an [`EventType::Custom`] event for an existing link, see
+    /// [`extension::ShortLinkAggregate::emit_custom`].
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{EventType, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// service.emit_custom(Slug::from("docs"), "note".to_string(), "reviewed".to_string())?;
+    /// let custom = EventType::Custom { name: "note".to_string(), payload: "reviewed".to_string() };
+    /// assert_eq!(service.get_events(&Slug::from("docs"))?[1].event_type, custom);
+    ///
+    /// let missing = service.emit_custom(Slug::from("missing"), "note".to_string(), String::new());
+    /// assert_eq!(missing, Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    #[cfg(feature = "extension")]
+    pub fn emit_custom(&mut self, slug: Slug, name: String, payload: String) -> Result<(), ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.emit_custom(name, payload);
+        self.after_command();
+
+        result
+    }
+
+    /// Deletes a short link like
+    /// [`ExtendedCommandHandler::handle_delete_short_link`](commands::ExtendedCommandHandler::handle_delete_short_link),
+    /// except that with `override_permanent` a permanent link is deleted too,
+    /// e.g. to take down abusive content. The override is recorded as an
+    /// [`EventType::PermanenceOverridden`] event.
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::SlugNotFound`] if the slug doesn't exist,
+    /// [`ShortenerError::LinkPermanent`] if the link is permanent and
+    /// `override_permanent` is not set, and [`ShortenerError::ProtectedLink`]
+    /// if its seed made it undeletable, whether overridden or not.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("flyer")))?;
+    /// service.handle_make_permanent(Slug::from("flyer"))?;
+    ///
+    /// assert_eq!(service.delete_short_link(Slug::from("flyer"), false), Err(ShortenerError::LinkPermanent));
+    /// service.delete_short_link(Slug::from("flyer"), true)?;
+    /// assert_eq!(service.handle_redirect(Slug::from("flyer")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn delete_short_link(&mut self, slug: Slug, override_permanent: bool) -> Result<(), ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.delete(override_permanent);
+        if result.is_ok() {
+            self.outcomes.remove(&slug);
+        }
+        self.after_command();
+
+        result
+    }
+
+    fn after_command(&mut self) {
+        self.commands_handled += 1;
+
+        if let Some(interval) = self.invariant_check_interval {
+            if cfg!(debug_assertions) && self.commands_handled.is_multiple_of(interval) {
+                if let Err(violations) = self.assert_invariants() {
+                    panic!("invariants violated after command {}: {:?}", self.commands_handled, violations);
+                }
+            }
+        }
+    }
+
+    /// Checks that every link's redirect counter equals the number of
+    /// redirect events recorded for it (plus any migrated redirects), and
+    /// that its URL is the latest one recorded, recounting from the event
+    /// store independently of the projection code.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_delete_short_link(Slug::from("docs"))?;
+    ///
+    /// assert_eq!(service.assert_invariants(), Ok(()));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn assert_invariants(&self) -> Result<(), Vec<InvariantViolation>> {
+        let mut violations = Vec::new();
+
+        for (slug, positions) in self.index.iter() {
+            let events: Vec<&Event> = positions.iter().map(|&position| &self.log[position]).collect();
+
+            // Only the events since the link was last created count; a
+            // deleted link must have no stats at all.
+            let lifetime_start = events.iter()
+                .rposition(|event| {
+                    matches!(event.event_type, EventType::ShortLinkCreated { .. } | EventType::ShortLinkDeleted)
+                });
+            let created = lifetime_start
+                .is_some_and(|start| matches!(events[start].event_type, EventType::ShortLinkCreated { .. }));
+            let redirect_events = events[lifetime_start.unwrap_or(0)..].iter()
+                .map(|event| match event.event_type {
+                    EventType::ShortLinkRedirected { .. } => 1,
+                    EventType::RedirectsMigrated(redirects) => redirects,
+                    _ => 0
+                })
+                .fold(0u64, u64::saturating_add);
+            let event_url = events[lifetime_start.unwrap_or(0)..].iter()
+                .filter_map(|event| match &event.event_type {
+                    EventType::ShortLinkCreated { url, .. } | EventType::TargetUrlUpdated(url) => Some(url),
+                    _ => None
+                })
+                .next_back();
+
+            match self.stats.get(slug) {
+                Some(_) if !created => {
+                    violations.push(InvariantViolation::OrphanStats { slug: slug.clone() });
+                }
+                None if created => {
+                    violations.push(InvariantViolation::MissingStats { slug: slug.clone() });
+                }
+                Some(stats) if stats.redirects != redirect_events => {
+                    violations.push(InvariantViolation::RedirectCountMismatch {
+                        slug: slug.clone(),
+                        events: redirect_events,
+                        counter: stats.redirects
+                    });
+                }
+                _ => {}
+            }
+
+            if let (Some(stats), Some(url)) = (self.stats.get(slug).filter(|_| created), event_url) {
+                if stats.link.url != *url {
+                    violations.push(InvariantViolation::UrlMismatch {
+                        slug: slug.clone(),
+                        events: url.clone(),
+                        stored: stats.link.url.clone()
+                    });
+                }
+            }
+        }
+
+        for slug in self.stats.keys() {
+            if self.index.get(slug).is_none() {
+                violations.push(InvariantViolation::OrphanStats { slug: slug.clone() });
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            violations.sort_by(|a, b| a.slug().cmp(b.slug()));
+            Err(violations)
+        }
+    }
+
+    /// Seeds an empty service from a plain `(slug, url, redirects)` map, as
+    /// kept by shorteners without event sourcing. Each entry becomes a
+    /// creation event, followed by a single compacted event carrying the
+    /// previously counted redirects.
+    ///
+    /// Entries with an invalid URL are skipped unless
+    /// [`MigrationOptions::lenient_urls`] is set, in which case they are
+    /// migrated and flagged as grandfathered. Invalid and repeated slugs are
+    /// skipped.
+    ///
+    /// ## Errors
+    ///
+    /// [`MigrationError::ServiceNotEmpty`] if the service already holds
+    /// events.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{
+    ///     InvalidUrlReason, MigrationError, MigrationOptions, MigrationSkipReason, Slug, UrlShortenerService,
+    /// };
+    ///
+    /// let legacy = [
+    ///     ("docs".to_string(), "https://example.com/docs".to_string(), Some(42)),
+    ///     ("ftp".to_string(), "ftp://example.com".to_string(), None),
+    /// ];
+    /// let mut service = UrlShortenerService::new();
+    ///
+    /// let report = service.migrate_from_map(legacy.clone(), MigrationOptions::default())?;
+    /// assert_eq!(report.migrated, [Slug::from("docs")]);
+    /// let skipped = MigrationSkipReason::InvalidUrl(InvalidUrlReason::InvalidScheme);
+    /// assert_eq!(report.skipped, [(Slug::from("ftp"), skipped)]);
+    /// assert_eq!(service.get_stats(Slug::from("docs")).map(|stats| stats.redirects), Ok(42));
+    ///
+    /// let again = service.migrate_from_map(legacy, MigrationOptions::default());
+    /// assert!(matches!(again, Err(MigrationError::ServiceNotEmpty)));
+    /// # Ok::<(), MigrationError>(())
+    /// ```
+    pub fn migrate_from_map(
+        &mut self,
+        links: impl IntoIterator<Item = (String, String, Option<u64>)>,
+        opts: MigrationOptions
+    ) -> Result<MigrationReport, MigrationError> {
+        if !self.index.is_empty() {
+            return Err(MigrationError::ServiceNotEmpty);
+        }
+
+        let mut report = MigrationReport::default();
+        for (slug, url, redirects) in links {
+            let (slug, url) = (Slug(slug), Url(url));
+
+            if domain::validate_slug(&slug).is_err() {
+                report.skipped.push((slug, MigrationSkipReason::InvalidSlug));
+                continue;
+            }
+
+            if self.index.get(&slug).is_some() {
+                report.skipped.push((slug, MigrationSkipReason::DuplicateSlug));
+                continue;
+            }
+
+            let grandfathered = match ServiceUrlValidator::default().validate(&url) {
+                Ok(()) => None,
+                Err(reason) if opts.lenient_urls => Some(reason),
+                Err(reason) => {
+                    report.skipped.push((slug, MigrationSkipReason::InvalidUrl(reason)));
+                    continue;
+                }
+            };
+
+            let created_at = opts.created_at.unwrap_or_else(|| self.clock.now());
+            let mut aggregate = ShortLinkAggregate::new(self);
+            aggregate.rehydrate_by_slug(&slug);
+            if aggregate.migrate(&url, redirects.unwrap_or(0), created_at, grandfathered).is_err() {
+                report.skipped.push((slug, MigrationSkipReason::DuplicateSlug));
+                continue;
+            }
+
+            if grandfathered.is_some() {
+                report.grandfathered.push(slug.clone());
+            }
+            report.migrated.push(slug);
+        }
+
+        Ok(report)
+    }
+
+    /// Creates well-known system links that are missing and brings existing
+    /// ones in line with their seeds, so it can run on every startup. Seeds
+    /// whose link already matches, with URLs compared in normalized form,
+    /// record no events. A seed whose URL drifted is handled according to
+    /// [`UrlShortenerService::with_bootstrap_drift`]; its tags and flags are
+    /// updated either way.
+    ///
+    /// Seeded links are protected by their [`SeedFlags`], which ordinary
+    /// commands refuse to undo with [`ShortenerError::ProtectedLink`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::ExtendedCommandHandler;
+    /// use url_shortener::{BootstrapLink, SeedFlags, ShortenerError, Slug, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// let flags = SeedFlags { undeletable: true, ..SeedFlags::default() };
+    /// let help = BootstrapLink::new("help", "https://example.com/help").with_tags(["system"]).with_flags(flags);
+    ///
+    /// let report = service.bootstrap(&[help.clone(), BootstrapLink::new("bad", "")]);
+    /// assert_eq!(report.created, [Slug::from("help")]);
+    /// assert_eq!(report.failed, [(Slug::from("bad"), ShortenerError::InvalidUrl)]);
+    /// assert_eq!(service.tags(&Slug::from("help")), ["system"]);
+    ///
+    /// let rerun = service.bootstrap(&[help, BootstrapLink::new("faq", "https://example.com")]);
+    /// assert_eq!((rerun.unchanged, rerun.created), (vec![Slug::from("help")], vec![Slug::from("faq")]));
+    /// let moved = service.bootstrap(&[BootstrapLink::new("help", "https://example.com/support").with_flags(flags)]);
+    /// assert_eq!((moved.drifted, moved.updated), (vec![Slug::from("help")], vec![Slug::from("help")]));
+    ///
+    /// assert_eq!(service.handle_delete_short_link(Slug::from("help")), Err(ShortenerError::ProtectedLink));
+    /// ```
+    pub fn bootstrap(&mut self, seeds: &[BootstrapLink]) -> BootstrapReport {
+        let mut report = BootstrapReport::default();
+
+        for seed in seeds {
+            let before = self.log.len();
+            let url = ServiceUrlValidator::default().normalize(&seed.url);
+            let drifted = match self.stats.get(&seed.slug) {
+                Some(stats) => stats.link.url != url,
+                None => {
+                    match self.seed_link(seed, |aggregate| aggregate.create_seeded(&seed.url, None).map(|_| ())) {
+                        Ok(()) => report.created.push(seed.slug.clone()),
+                        Err(error) => report.failed.push((seed.slug.clone(), error))
+                    }
+                    continue;
+                }
+            };
+
+            if drifted && self.bootstrap_drift == BootstrapDrift::Correct {
+                match self.seed_link(seed, |aggregate| aggregate.update_target(&seed.url).map(|_| ())) {
+                    Ok(()) => report.corrected.push(seed.slug.clone()),
+                    Err(error) => report.failed.push((seed.slug.clone(), error))
+                }
+                continue;
+            }
+
+            if let Err(error) = self.seed_link(seed, |_| Ok(())) {
+                report.failed.push((seed.slug.clone(), error));
+                continue;
+            }
+            if drifted {
+                report.drifted.push(seed.slug.clone());
+            }
+            if self.log.len() > before {
+                report.updated.push(seed.slug.clone());
+            } else if !drifted {
+                report.unchanged.push(seed.slug.clone());
+            }
+        }
+
+        report
+    }
+
+    /// Runs `change` on the aggregate of a seed's link, then applies the
+    /// seed's tags and flags. Permanence comes last, so that a corrected URL
+    /// is in place before the link is frozen.
+    fn seed_link(
+        &mut self,
+        seed: &BootstrapLink,
+        change: impl FnOnce(&mut ShortLinkAggregate) -> Result<(), ShortenerError>
+    ) -> Result<(), ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&seed.slug);
+        let protection = LinkProtection { reserved: seed.flags.reserved, undeletable: seed.flags.undeletable };
+        let result = change(&mut aggregate)
+            .and_then(|()| aggregate.set_tags(&seed.tags))
+            .and_then(|_| aggregate.set_protection(protection))
+            .and_then(|_| match seed.flags.permanent {
+                true => aggregate.make_permanent(),
+                false => Ok(commands::CommandOutcome::Unchanged)
+            });
+        self.after_command();
+
+        result.map(|_| ())
+    }
+
+    /// Returns the tags a [`BootstrapLink`] gave a link, empty if it has
+    /// none.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::{BootstrapLink, Slug, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.bootstrap(&[BootstrapLink::new("status", "https://status.example.com").with_tags(["system", "ops"])]);
+    ///
+    /// assert_eq!(service.tags(&Slug::from("status")), ["system", "ops"]);
+    /// assert!(service.tags(&Slug::from("missing")).is_empty());
+    /// ```
+    pub fn tags(&self, slug: &Slug) -> &[String] {
+        self.seeded.tags.get(slug).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the protection a [`BootstrapLink`] gave a slug. A reserved
+    /// slug stays reserved after its link is deleted.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::{BootstrapLink, LinkProtection, SeedFlags, Slug, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// let flags = SeedFlags { reserved: true, ..SeedFlags::default() };
+    /// service.bootstrap(&[BootstrapLink::new("status", "https://status.example.com").with_flags(flags)]);
+    ///
+    /// assert_eq!(service.protection(&Slug::from("status")), LinkProtection { reserved: true, undeletable: false });
+    /// assert_eq!(service.protection(&Slug::from("missing")), LinkProtection::default());
+    /// ```
+    pub fn protection(&self, slug: &Slug) -> LinkProtection {
+        self.seeded.protections.get(slug).copied().unwrap_or_default()
+    }
+
+    /// Serializes the whole event log to JSON, as an array of events in the
+    /// order they were recorded.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let json = service.export_events_json();
+    /// assert!(json.starts_with('[') && json.contains("\"docs\""));
+    /// assert_eq!(UrlShortenerService::new().export_events_json(), "[]");
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn export_events_json(&self) -> String {
+        serde_json::to_string(&self.log).expect("events always serialize")
+    }
+
+    /// Rebuilds a service from a log written by
+    /// [`UrlShortenerService::export_events_json`], replaying each event
+    /// through its link's aggregate in order. The returned service has the
+    /// default configuration; `with_*` settings can be applied afterwards.
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::InvalidEventLog`] if the JSON is malformed or an
+    /// event doesn't fit its link's history, e.g. a redirect before the link
+    /// was created.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let restored = UrlShortenerService::import_events_json(&service.export_events_json())?;
+    /// assert_eq!(restored.get_stats(Slug::from("docs")), service.get_stats(Slug::from("docs")));
+    /// assert!(matches!(UrlShortenerService::import_events_json("[{"), Err(ShortenerError::InvalidEventLog)));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn import_events_json(json: &str) -> Result<UrlShortenerService, ShortenerError> {
+        let events: Vec<Event> = serde_json::from_str(json)
+            .map_err(|_| ShortenerError::InvalidEventLog)?;
+
+        let mut service = Self::new();
+        for event in &events {
+            service.replay_imported(event).map_err(|_| ShortenerError::InvalidEventLog)?;
+        }
+
+        Ok(service)
+    }
+
+    /// Writes the whole event log as JSON Lines: one JSON event per line, in
+    /// the order they were recorded.
+    ///
+    /// ## Errors
+    ///
+    /// Any error of the writer.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// service.handle_redirect(Slug::from("docs")).unwrap();
+    ///
+    /// let mut exported = Vec::new();
+    /// service.export_events(&mut exported)?;
+    /// assert_eq!(exported.split(|&byte| byte == b'\n').filter(|line| !line.is_empty()).count(), 2);
+    ///
+    /// // Errors of the writer are passed on.
+    /// assert!(service.export_events(&mut &mut [0u8; 8][..]).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn export_events(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        for event in &self.log {
+            serde_json::to_writer(&mut *w, event)?;
+            w.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds a service from JSON Lines written by
+    /// [`UrlShortenerService::export_events`], like
+    /// [`UrlShortenerService::import_events_json`]. Blank lines are skipped.
+    /// Same as [`UrlShortenerService::import_events_with`] in
+    /// [`ImportMode::Strict`].
+    ///
+    /// ## Errors
+    ///
+    /// See [`ImportError`]; errors name the first offending line.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ImportError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// let mut exported = Vec::new();
+    /// service.export_events(&mut exported).unwrap();
+    ///
+    /// let restored = UrlShortenerService::import_events(&mut exported.as_slice())?;
+    /// assert_eq!(restored.get_stats(Slug::from("docs")), service.get_stats(Slug::from("docs")));
+    ///
+    /// exported.extend_from_slice(b"not json\n");
+    /// let error = UrlShortenerService::import_events(&mut exported.as_slice()).err().unwrap();
+    /// assert!(matches!(error, ImportError::Parse { line: 2, .. }));
+    /// # Ok::<(), ImportError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn import_events(r: &mut impl std::io::Read) -> Result<UrlShortenerService, ImportError> {
+        Self::import_events_with(r, ImportMode::Strict).map(|(service, _)| service)
+    }
+
+    /// Rebuilds a service from JSON Lines like
+    /// [`UrlShortenerService::import_events`], checking each event against
+    /// its link's history. In [`ImportMode::Lenient`] events that don't fit
+    /// are skipped instead of failing the import; the first skipped event
+    /// of each link is returned, in stream order, as
+    /// [`UrlShortenerService::validate_events`] reports them.
+    ///
+    /// ## Errors
+    ///
+    /// See [`ImportError`]; errors name the first offending line.
+    /// [`ImportError::InvalidEvent`] only occurs in [`ImportMode::Strict`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ImportError, ImportMode, Slug, TransitionError, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// service.handle_redirect(Slug::from("docs")).unwrap();
+    /// let mut exported = Vec::new();
+    /// service.export_events(&mut exported).unwrap();
+    /// let doubled = [exported.as_slice(), exported.as_slice()].concat();
+    ///
+    /// let error = UrlShortenerService::import_events_with(&mut doubled.as_slice(), ImportMode::Strict).err().unwrap();
+    /// assert!(matches!(error, ImportError::InvalidEvent { line: 3, .. }));
+    ///
+    /// let (restored, skipped) =
+    ///     UrlShortenerService::import_events_with(&mut doubled.as_slice(), ImportMode::Lenient)?;
+    /// assert_eq!((skipped[0].sequence, skipped[0].error), (3, TransitionError::AlreadyCreated));
+    /// assert_eq!(restored.get_stats(Slug::from("docs")).unwrap().redirects, 2);
+    /// # Ok::<(), ImportError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn import_events_with(
+        r: &mut impl std::io::Read,
+        mode: ImportMode,
+    ) -> Result<(UrlShortenerService, Vec<InvalidTransition>), ImportError> {
+        let mut service = Self::new();
+        let mut skipped = Vec::new();
+        let mut reported = HashSet::new();
+        Self::read_event_lines(r, |line, sequence, event| {
+            let Err(error) = service.replay_imported(&event) else {
+                return Ok(());
+            };
+
+            let transition = InvalidTransition { slug: event.slug, sequence, event_type: event.event_type, error };
+            match mode {
+                ImportMode::Strict => return Err(ImportError::InvalidEvent { line, transition }),
+                ImportMode::Lenient if reported.insert(transition.slug.clone()) => skipped.push(transition),
+                ImportMode::Lenient => {}
+            }
+            Ok(())
+        })?;
+
+        Ok((service, skipped))
+    }
+
+    /// Checks JSON Lines written by [`UrlShortenerService::export_events`]
+    /// without importing them: each link's events are dry-run through its
+    /// aggregate's states, and the first event of each link that doesn't
+    /// fit its history is returned, in stream order. Events that don't fit
+    /// are left out of the history the later ones are checked against, like
+    /// [`ImportMode::Lenient`] does. An empty result means the stream
+    /// imports in [`ImportMode::Strict`].
+    ///
+    /// ## Errors
+    ///
+    /// [`ImportError::Io`] and [`ImportError::Parse`] like
+    /// [`UrlShortenerService::import_events`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ImportError, Slug, TransitionError, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// service.handle_redirect(Slug::from("docs")).unwrap();
+    /// service.handle_delete_short_link(Slug::from("docs")).unwrap();
+    /// let mut exported = Vec::new();
+    /// service.export_events(&mut exported).unwrap();
+    /// assert!(UrlShortenerService::validate_events(&mut exported.as_slice())?.is_empty());
+    ///
+    /// // The redirect, moved after the delete.
+    /// let lines: Vec<&[u8]> = exported.split_inclusive(|&byte| byte == b'\n').collect();
+    /// let reordered = [lines[0], lines[2], lines[1]].concat();
+    /// let invalid = UrlShortenerService::validate_events(&mut reordered.as_slice())?;
+    /// assert_eq!((invalid[0].sequence, invalid[0].error), (3, TransitionError::Deleted));
+    /// # Ok::<(), ImportError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn validate_events(r: &mut impl std::io::Read) -> Result<Vec<InvalidTransition>, ImportError> {
+        let mut states: HashMap<Slug, domain::LinkState> = HashMap::new();
+        let mut invalid = Vec::new();
+        let mut reported = HashSet::new();
+        Self::read_event_lines(r, |_, sequence, event| {
+            let state = states.entry(event.slug.clone()).or_insert_with(|| domain::LinkState::new(event.slug.clone()));
+            match state.check(&event) {
+                Ok(()) => state.apply(&event),
+                Err(error) if reported.insert(event.slug.clone()) => invalid.push(InvalidTransition {
+                    slug: event.slug,
+                    sequence,
+                    event_type: event.event_type,
+                    error
+                }),
+                Err(_) => {}
+            }
+            Ok(())
+        })?;
+
+        Ok(invalid)
+    }
+
+    /// Parses JSON Lines into events, skipping blank lines, and passes each
+    /// with its one-based line number and stream sequence to `f`.
+    #[cfg(feature = "serde")]
+    fn read_event_lines(
+        r: &mut impl std::io::Read,
+        mut f: impl FnMut(usize, u64, Event) -> Result<(), ImportError>
+    ) -> Result<(), ImportError> {
+        use std::io::BufRead;
+
+        let mut sequence = 0;
+        for (index, line) in std::io::BufReader::new(r).lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.map_err(ImportError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event: Event = serde_json::from_str(&line)
+                .map_err(|error| ImportError::Parse { line: line_number, message: error.to_string() })?;
+            sequence += 1;
+            f(line_number, sequence, event)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the event log together with the read models to `path`, so that
+    /// [`UrlShortenerService::load_from_path`] can restore the service
+    /// without replaying every event. Configuration and outcome counters
+    /// are not saved.
+    ///
+    /// ## Errors
+    ///
+    /// Any error creating or writing the file.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// let path = std::env::temp_dir().join(format!("url-shortener-save-{}", std::process::id()));
+    ///
+    /// service.save_to_path(&path)?;
+    /// assert!(std::fs::metadata(&path)?.len() > 0);
+    /// std::fs::remove_file(&path)?;
+    ///
+    /// assert!(service.save_to_path(path.join("no such directory")).is_err());
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn save_to_path(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let state = SavedState {
+            events: self.log.clone(),
+            stats: self.stats.clone(),
+            serving_hints: self.serving_hints.clone(),
+            link_health: self.link_health.clone(),
+            permanent_slugs: self.permanent_slugs.clone(),
+            expiries: self.expiries.clone(),
+            redirect_limits: self.redirect_limits.clone(),
+            consumed_slugs: self.consumed_slugs.clone()
+        };
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "{STATE_FILE_HEADER} {STATE_FILE_VERSION}")?;
+        serde_json::to_writer(&mut file, &state)?;
+        file.flush()
+    }
+
+    /// Restores a service saved by [`UrlShortenerService::save_to_path`].
+    /// The returned service has the default configuration; `with_*`
+    /// settings can be applied afterwards. Files of an older format version
+    /// are migrated: their read models are rebuilt from the event log as by
+    /// [`UrlShortenerService::rebuild_read_model`].
+    ///
+    /// ## Errors
+    ///
+    /// See [`LoadError`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{LoadError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs"))).unwrap();
+    /// let path = std::env::temp_dir().join(format!("url-shortener-load-{}", std::process::id()));
+    /// service.save_to_path(&path).map_err(LoadError::Io)?;
+    ///
+    /// let loaded = UrlShortenerService::load_from_path(&path)?;
+    /// assert_eq!(loaded.get_stats(Slug::from("docs")), service.get_stats(Slug::from("docs")));
+    ///
+    /// std::fs::write(&path, "not a state file").map_err(LoadError::Io)?;
+    /// assert!(matches!(UrlShortenerService::load_from_path(&path), Err(LoadError::Malformed(_))));
+    /// std::fs::remove_file(&path).map_err(LoadError::Io)?;
+    /// # Ok::<(), LoadError>(())
+    /// ```
+    #[cfg(feature = "serde")]
+    pub fn load_from_path(path: impl AsRef<std::path::Path>) -> Result<UrlShortenerService, LoadError> {
+        use std::io::BufRead;
+
+        let mut reader = std::io::BufReader::new(std::fs::File::open(path).map_err(LoadError::Io)?);
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(LoadError::Io)?;
+
+        let version = header.trim_end()
+            .strip_prefix(STATE_FILE_HEADER)
+            .and_then(|rest| rest.strip_prefix(' '))
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or_else(|| LoadError::Malformed("missing state file header".to_string()))?;
+        if !(OLDEST_STATE_FILE_VERSION..=STATE_FILE_VERSION).contains(&version) {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let state: SavedState = serde_json::from_reader(reader)
+            .map_err(|error| LoadError::Malformed(error.to_string()))?;
+
+        let mut service = Self::new();
+        for (position, event) in state.events.iter().enumerate() {
+            let id = service.index.intern(&event.slug);
+            service.index.push(id, position);
+        }
+        service.creation_order = CreationOrder::from_events(&state.events);
+        service.seeded = SeededLinks::from_events(&state.events);
+        service.stats_grants = StatsGrants::from_events(&state.events);
+        service.recent_clicks = RecentClicks::from_events(&state.events);
+        service.expiry_order = ExpiryOrder::from_events(&state.events);
+        service.log = state.events;
+        service.stats = state.stats;
+        service.serving_hints = state.serving_hints;
+        service.link_health = state.link_health;
+        service.permanent_slugs = state.permanent_slugs;
+        service.expiries = state.expiries;
+        service.redirect_limits = state.redirect_limits;
+        service.consumed_slugs = state.consumed_slugs;
+
+        service.folded_slugs = Self::build_folded_index(&service.stats, &service.leniency);
+        let links: Vec<ShortLink> = service.stats.values().map(|stats| stats.link.clone()).collect();
+        for link in &links {
+            service.index_url(&link.url, &link.slug);
+        }
+        service.totals = Totals {
+            links: service.stats.len() as u64,
+            redirects: service.stats.values().fold(0, |sum, stats| sum.saturating_add(stats.redirects))
+        };
+        if version < STATE_FILE_VERSION {
+            service.rebuild_read_model();
+        }
+
+        Ok(service)
+    }
+
+    /// Records an imported event through its link's aggregate.
+    fn replay_imported(&mut self, event: &Event) -> Result<(), TransitionError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&event.slug);
+        aggregate.replay(event)
+    }
+
+    /// Describes what this service instance supports, derived from its
+    /// configuration.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::{DeadLinkPolicy, UrlShortenerService};
+    ///
+    /// let service =
+    ///     UrlShortenerService::new().with_leaderboard(10).with_dead_link_policy(DeadLinkPolicy::ReturnError);
+    /// let capabilities = service.capabilities();
+    ///
+    /// assert_eq!(capabilities.leaderboard_size, 10);
+    /// assert_eq!(capabilities.dead_link_policy, DeadLinkPolicy::ReturnError);
+    /// assert_eq!(capabilities.projection_queue, None);
+    /// ```
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            lookup_leniency: self.leniency,
+            slug_display: self.display_policy.clone(),
+            leaderboard_size: self.leaderboard_size,
+            dead_link_policy: self.dead_link_policy,
+            slug_generation_attempts: self.slug_generation_attempts,
+            snapshot_interval: self.snapshot_interval,
+            projection_queue: self.projection_queue.as_ref().map(|queue| ProjectionQueueConfig {
+                capacity: queue.capacity,
+                overflow: queue.overflow
+            }),
+            invariant_check_interval: self.invariant_check_interval,
+            max_slug_len: domain::MAX_SLUG_LEN,
+            strict_url: cfg!(feature = "strict-url"),
+            serde: cfg!(feature = "serde"),
+            extension: cfg!(feature = "extension")
+        }
+    }
+
+    /// Applies an event to every read model, or queues it for the deferred
+    /// ones if a projection queue is configured.
+    fn project_event(&mut self, event: &Event) {
+        match &event.event_type {
+            EventType::ShortLinkCreated { url, expires_at } => {
+                if let Some(expires_at) = expires_at {
+                    self.expiries.insert(event.slug.clone(), *expires_at);
+                }
+                let stats = Stats {
+                    link: ShortLink { slug: event.slug.clone(), url: url.clone() },
+                    redirects: 0,
+                    enabled: true,
+                    created_at: event.timestamp,
+                    last_redirected_at: None
+                };
+
+                self.stats.insert(event.slug.clone(), stats);
+                self.creation_order.insert(&event.slug);
+                self.recent_clicks.apply(event);
+                self.expiry_order.apply(event);
+                self.totals.links += 1;
+                Self::index_folded_slug(&mut self.folded_slugs, &event.slug, &self.leniency);
+                self.index_url(url, &event.slug);
+            }
+            EventType::ShortLinkRedirected { .. } => {
+                self.recent_clicks.apply(event);
+                if let Some(stats) = self.stats.get_mut(&event.slug) {
+                    stats.redirects = stats.redirects.saturating_add(1);
+                    stats.last_redirected_at = Some(event.timestamp);
+                    self.totals.redirects = self.totals.redirects.saturating_add(1);
+                }
+            }
+            EventType::ServingHintsSet(hints) => {
+                self.serving_hints.insert(event.slug.clone(), hints.clone());
+            }
+            EventType::ServingHintsCleared => {
+                self.serving_hints.remove(&event.slug);
+            }
+            EventType::RedirectsMigrated(redirects) => {
+                if let Some(stats) = self.stats.get_mut(&event.slug) {
+                    stats.redirects = stats.redirects.saturating_add(*redirects);
+                    self.totals.redirects = self.totals.redirects.saturating_add(*redirects);
+                }
+            }
+            EventType::LinkMarkedDead(evidence) => {
+                self.link_health.entry(event.slug.clone()).or_default().dead = Some(evidence.clone());
+            }
+            EventType::LinkMarkedAlive => {
+                if let Some(health) = self.link_health.get_mut(&event.slug) {
+                    health.dead = None;
+                }
+            }
+            EventType::DeadLinkPolicySet(policy) => {
+                self.link_health.entry(event.slug.clone()).or_default().policy = *policy;
+            }
+            EventType::MarkedPermanent => {
+                self.permanent_slugs.insert(event.slug.clone());
+            }
+            EventType::PermanenceOverridden => {
+                self.permanent_slugs.remove(&event.slug);
+            }
+            EventType::ProtectionSet(_) | EventType::TagsSet(_) => {
+                self.seeded.apply(event);
+            }
+            EventType::StatsTokenIssued { .. } | EventType::StatsTokenRevoked { .. } => {
+                self.stats_grants.apply(event);
+            }
+            EventType::TargetUrlUpdated(url) => {
+                if let Some(stats) = self.stats.get_mut(&event.slug) {
+                    let old_url = std::mem::replace(&mut stats.link.url, url.clone());
+                    self.unindex_url(&old_url, &event.slug);
+                    self.index_url(url, &event.slug);
+                }
+                if let Some(health) = self.link_health.get_mut(&event.slug) {
+                    health.dead = None;
+                }
+            }
+            EventType::ShortLinkDisabled | EventType::ShortLinkEnabled => {
+                if let Some(stats) = self.stats.get_mut(&event.slug) {
+                    stats.enabled = matches!(event.event_type, EventType::ShortLinkEnabled);
+                }
+            }
+            EventType::RedirectLimitSet(limit) => {
+                self.redirect_limits.insert(event.slug.clone(), *limit);
+            }
+            EventType::ExpirySet(expires_at) => {
+                match expires_at {
+                    Some(expires_at) => self.expiries.insert(event.slug.clone(), *expires_at),
+                    None => self.expiries.remove(&event.slug)
+                };
+                self.expiry_order.apply(event);
+            }
+            EventType::ExpiryNoticeSent(_) => self.expiry_order.apply(event),
+            EventType::OneTimeLinkConsumed => {
+                self.consumed_slugs.insert(event.slug.clone());
+            }
+            EventType::MarkedOneTime | EventType::UrlGrandfathered(_) | EventType::TimestampsBackfilled(_) => {}
+            EventType::Custom { name, payload } => {
+                #[cfg(feature = "extension")]
+                for projection in &mut self.custom_projections {
+                    projection(&event.slug, name, payload);
+                }
+            }
+            EventType::ShortLinkDeleted => {
+                if let Some(stats) = self.stats.remove(&event.slug) {
+                    self.unindex_url(&stats.link.url, &event.slug);
+                    self.totals.links = self.totals.links.saturating_sub(1);
+                    self.totals.redirects = self.totals.redirects.saturating_sub(stats.redirects);
+                }
+                self.creation_order.remove(&event.slug);
+                self.seeded.apply(event);
+                self.stats_grants.apply(event);
+                self.recent_clicks.apply(event);
+                self.expiry_order.apply(event);
+                self.expiries.remove(&event.slug);
+                self.redirect_limits.remove(&event.slug);
+                self.consumed_slugs.remove(&event.slug);
+                self.serving_hints.remove(&event.slug);
+                self.link_health.remove(&event.slug);
+                self.permanent_slugs.remove(&event.slug);
+                self.unindex_folded_slug(&event.slug);
+            }
+        }
+
+        let Some(queue) = self.projection_queue.as_mut() else {
+            self.project_deferred(event);
+            return;
+        };
+
+        if queue.events.len() >= queue.capacity {
+            match queue.overflow {
+                QueueOverflow::DropWithCounter => {
+                    queue.dropped += 1;
+                    return;
+                }
+                QueueOverflow::AutoPump => {
+                    self.pump_projections(1);
+                }
+            }
+        }
+
+        if let Some(queue) = self.projection_queue.as_mut() {
+            queue.events.push_back(event.clone());
+        }
+    }
+
+    /// Applies an event to the projections that may be deferred.
+    fn project_deferred(&mut self, event: &Event) {
+        match &event.event_type {
+            EventType::ShortLinkRedirected { .. } | EventType::RedirectsMigrated(_) => {
+                if let Some(redirects) = self.stats.get(&event.slug).map(|stats| stats.redirects) {
+                    self.update_leaderboard(&event.slug, redirects);
+                }
+            }
+            EventType::ShortLinkDeleted
+                if self.leaderboard.entries.iter().any(|(slug, _)| *slug == event.slug) => {
+                self.reconcile_leaderboards();
+            }
+            _ => {}
+        }
+    }
+
+    /// Moves a link whose redirect counter grew into the leaderboard if it now
+    /// ranks among the top entries.
+    ///
+    /// Counters only ever grow and the link's exact count is passed in, so a
+    /// link that fell off the board and later climbs back is placed correctly
+    /// without a reconcile.
+    fn update_leaderboard(&mut self, slug: &Slug, redirects: u64) {
+        if self.leaderboard_size == 0 {
+            return;
+        }
+
+        let entries = &mut self.leaderboard.entries;
+        entries.retain(|(entry, _)| entry != slug);
+        entries.push((slug.clone(), redirects));
+        entries.sort_by(|(a_slug, a), (b_slug, b)| b.cmp(a).then_with(|| a_slug.cmp(b_slug)));
+        entries.truncate(self.leaderboard_size);
+        self.leaderboard.updates_since_reconcile += 1;
+    }
+
+    /// Recomputes the leaderboard from every link's [`Stats`], discarding the
+    /// incrementally maintained state.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{QueueOverflow, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// // A full queue drops events, so the leaderboard falls behind.
+    /// let mut service =
+    ///     UrlShortenerService::new().with_leaderboard(3).with_projection_queue(1, QueueOverflow::DropWithCounter);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.pump_projections(10);
+    /// assert_eq!(service.leaderboard().map(|board| board.entries.len()), Some(0));
+    ///
+    /// service.reconcile_leaderboards();
+    /// assert_eq!(service.leaderboard().map(|board| board.entries), Some(vec![(Slug::from("docs"), 1)]));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn reconcile_leaderboards(&mut self) {
+        let mut entries: Vec<(Slug, u64)> = self.stats.values()
+            .filter(|stats| stats.redirects > 0)
+            .map(|stats| (stats.link.slug.clone(), stats.redirects))
+            .collect();
+        entries.sort_by(|(a_slug, a), (b_slug, b)| b.cmp(a).then_with(|| a_slug.cmp(b_slug)));
+        entries.truncate(self.leaderboard_size);
+
+        self.leaderboard = Leaderboard {
+            entries,
+            updates_since_reconcile: 0
+        };
+    }
+
+    /// Returns the raw event stream of a slug in recorded order, including
+    /// events of earlier links deleted under the same slug.
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::SlugNotFound`] if no event was ever recorded for the
+    /// slug.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{EventType, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let events = service.get_events(&Slug::from("docs"))?;
+    /// assert!(matches!(events[0].event_type, EventType::ShortLinkCreated { .. }));
+    /// assert_eq!((events[1].position, events.len()), (1, 2));
+    /// assert_eq!(service.get_events(&Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_events(&self, slug: &Slug) -> Result<Vec<EventRecord>, ShortenerError> {
+        let id = self.index.get(slug).ok_or(ShortenerError::SlugNotFound)?;
+
+        Ok(domain::EventBroker::iter_by_id_from(self, id, 0)
+            .enumerate()
+            .map(|(position, event)| EventRecord {
+                position,
+                timestamp: event.timestamp,
+                event_type: event.event_type.clone()
+            })
+            .collect())
+    }
+
+    /// Returns how many redirects of a link went to each destination it
+    /// served, in the order the destinations were first served. Counts come
+    /// from the URL recorded with each redirect, so a click recorded after
+    /// the link was pointed elsewhere still counts for the URL it was served
+    /// with. Migrated redirects carry no URL and are left out.
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::SlugNotFound`] if the slug has no link.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("ab")))?;
+    /// service.handle_redirect(Slug::from("ab"))?;
+    /// service.handle_update_target(Slug::from("ab"), Url::from("https://b.com"))?;
+    /// service.handle_redirect(Slug::from("ab"))?;
+    /// service.handle_redirect(Slug::from("ab"))?;
+    ///
+    /// assert_eq!(
+    ///     service.get_redirects_by_url(&Slug::from("ab"))?,
+    ///     vec![(Url::from("https://a.com"), 1), (Url::from("https://b.com"), 2)]
+    /// );
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_redirects_by_url(&self, slug: &Slug) -> Result<Vec<(Url, u64)>, ShortenerError> {
+        if !self.stats.contains_key(slug) {
+            return Err(ShortenerError::SlugNotFound);
+        }
+        let id = self.index.get(slug).ok_or(ShortenerError::SlugNotFound)?;
+
+        let mut counts: Vec<(Url, u64)> = Vec::new();
+        for event in domain::EventBroker::iter_by_id_from(self, id, 0) {
+            match &event.event_type {
+                // Only the current link's redirects count.
+                EventType::ShortLinkCreated { .. } => counts.clear(),
+                EventType::ShortLinkRedirected { url, .. } => {
+                    match counts.iter_mut().find(|(served, _)| served == url) {
+                        Some((_, count)) => *count += 1,
+                        None => counts.push((url.clone(), 1))
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(counts)
+    }
+
+    /// Measures how bursty a link's traffic is from the time between its
+    /// consecutive redirects. By default only the latest redirects kept per
+    /// link are considered, see [`UrlShortenerService::with_recent_clicks`];
+    /// with `exact` every redirect of the link is, by replaying its history.
+    /// Migrated redirects carry no time and are left out.
+    ///
+    /// ## Errors
+    ///
+    /// [`ShortenerError::SlugNotFound`] if the slug has no link.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ClickIntervals, FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(SystemTime::UNIX_EPOCH));
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// let intervals = service.get_click_interval_stats(&Slug::from("docs"), false)?;
+    /// assert_eq!(intervals, ClickIntervals::InsufficientData { clicks: 0 });
+    ///
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// let ClickIntervals::Measured(stats) = service.get_click_interval_stats(&Slug::from("docs"), false)? else {
+    ///     panic!("two clicks have an interval");
+    /// };
+    /// assert_eq!((stats.count, stats.max), (1, Duration::ZERO));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_click_interval_stats(&self, slug: &Slug, exact: bool) -> Result<ClickIntervals, ShortenerError> {
+        if !self.stats.contains_key(slug) {
+            return Err(ShortenerError::SlugNotFound);
+        }
+
+        let clicks: Vec<SystemTime> = if exact {
+            let mut clicks = Vec::new();
+            for event in domain::EventBroker::iter_by_slug(self, slug) {
+                match event.event_type {
+                    EventType::ShortLinkCreated { .. } => clicks.clear(),
+                    EventType::ShortLinkRedirected { .. } => clicks.push(event.timestamp),
+                    _ => {}
+                }
+            }
+            clicks
+        } else {
+            self.recent_clicks.clicks.get(slug).map_or_else(Vec::new, |clicks| clicks.iter().copied().collect())
+        };
+
+        if clicks.len() < 2 {
+            return Ok(ClickIntervals::InsufficientData { clicks: clicks.len() });
+        }
+
+        // A clock set back between two clicks counts as no time passing.
+        let mut intervals: Vec<Duration> = clicks.windows(2)
+            .map(|pair| pair[1].duration_since(pair[0]).unwrap_or_default())
+            .collect();
+        intervals.sort();
+        let percentile = |p: usize| intervals[(intervals.len() * p).div_ceil(100).max(1) - 1];
+
+        Ok(ClickIntervals::Measured(IntervalStats {
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: intervals[intervals.len() - 1],
+            count: intervals.len()
+        }))
+    }
+
+    /// Returns the compact id of a slug, or `None` if it never had a link.
+    /// Ids stay the same when the link is deleted and created again.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, SlugId, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    /// service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b")))?;
+    /// service.handle_delete_short_link(Slug::from("a"))?;
+    ///
+    /// assert_eq!(service.slug_id(&Slug::from("a")), Some(SlugId(0)));
+    /// assert_eq!(service.slug_for_id(SlugId(1)), Some(&Slug::from("b")));
+    /// assert_eq!(service.events_since(2)[0].slug_id, SlugId(0));
+    /// assert_eq!(service.slug_id(&Slug::from("missing")), None);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn slug_id(&self, slug: &Slug) -> Option<SlugId> {
+        self.index.get(slug)
+    }
+
+    /// Returns the slug with the given id, see
+    /// [`UrlShortenerService::slug_id`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    ///
+    /// let id = service.slug_id(&Slug::from("a")).expect("the link exists");
+    /// assert_eq!(service.slug_for_id(id), Some(&Slug::from("a")));
+    /// assert_eq!(service.slug_for_id(url_shortener::SlugId(99)), None);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn slug_for_id(&self, id: SlugId) -> Option<&Slug> {
+        self.index.slug(id)
+    }
+
+    /// Returns every event recorded after `sequence`, across all links and in
+    /// the order they were recorded. Sequence numbers start at 1, so
+    /// `events_since(0)` returns the whole stream; passing the sequence of
+    /// the last event seen tails the stream from there.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    /// service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b")))?;
+    /// service.handle_redirect(Slug::from("a"))?;
+    ///
+    /// let tail: Vec<_> = service.events_since(1).into_iter().map(|event| (event.sequence, event.slug)).collect();
+    /// assert_eq!(tail, [(2, Slug::from("b")), (3, Slug::from("a"))]);
+    /// assert!(service.events_since(3).is_empty());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn events_since(&self, sequence: u64) -> Vec<SequencedEvent> {
+        let start = usize::try_from(sequence).unwrap_or(usize::MAX).min(self.log.len());
+
+        self.log[start..].iter()
+            .zip(&self.index.event_ids[start..])
+            .zip(start as u64 + 1..)
+            .map(|((event, &slug_id), sequence)| SequencedEvent {
+                sequence,
+                slug: event.slug.clone(),
+                slug_id,
+                timestamp: event.timestamp,
+                event_type: event.event_type.clone()
+            })
+            .collect()
+    }
+
+    /// Returns the high-water mark of every link with events: how many of its
+    /// events this log holds and their fingerprint. A copy of the log passes
+    /// it to the source's [`UrlShortenerService::diff_events`] to learn what
+    /// it is missing.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// assert_eq!(service.version_vector()[&Slug::from("docs")].events, 2);
+    /// assert!(UrlShortenerService::new().version_vector().is_empty());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn version_vector(&self) -> VersionVector {
+        self.index.ids.iter()
+            .map(|(slug, &id)| {
+                let positions = self.index.positions(id);
+                (slug.clone(), SlugVersion { events: positions.len(), fingerprint: self.fingerprint(positions) })
+            })
+            .collect()
+    }
+
+    /// Computes which events a copy of this log is missing, given the
+    /// copy's [`UrlShortenerService::version_vector`]: for each link, those
+    /// after the copy's high-water mark. Fetch them with
+    /// [`UrlShortenerService::sync_events`] and hand both to the copy's
+    /// [`UrlShortenerService::apply_sync_plan`].
+    ///
+    /// ## Errors
+    ///
+    /// [`SyncError::Diverged`] with the first such link, by slug, if the
+    /// copy holds events of a link that this log doesn't, e.g. because it
+    /// recorded commands of its own.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut production = UrlShortenerService::new();
+    /// production.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// production.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let mut staging = UrlShortenerService::new();
+    /// let plan = production.diff_events(&staging.version_vector()).unwrap();
+    /// assert_eq!(plan.events(), 2);
+    /// staging.apply_sync_plan(&plan, &production.sync_events(&plan)).unwrap();
+    ///
+    /// assert_eq!(staging.get_stats(Slug::from("docs")), production.get_stats(Slug::from("docs")));
+    /// assert!(production.diff_events(&staging.version_vector()).unwrap().is_empty());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn diff_events(&self, copy: &VersionVector) -> Result<SyncPlan, SyncError> {
+        for (slug, version) in copy {
+            let positions = self.index.get(slug).map_or(&[][..], |id| self.index.positions(id));
+            let shared = positions.get(..version.events).ok_or_else(|| SyncError::Diverged(slug.clone()))?;
+            if self.fingerprint(shared) != version.fingerprint {
+                return Err(SyncError::Diverged(slug.clone()));
+            }
+        }
+
+        let mut links: Vec<SyncRange> = self.index.ids.iter()
+            .map(|(slug, &id)| SyncRange {
+                slug: slug.clone(),
+                from: copy.get(slug).map_or(0, |version| version.events),
+                to: self.index.positions(id).len()
+            })
+            .filter(|range| range.from < range.to)
+            .collect();
+        links.sort_by(|a, b| a.slug.cmp(&b.slug));
+
+        Ok(SyncPlan { links })
+    }
+
+    /// Returns the events listed by a plan of
+    /// [`UrlShortenerService::diff_events`], in the order they were
+    /// recorded here.
+    ///
+    /// ## Examples
+    ///
+    /// See [`UrlShortenerService::diff_events`].
+    pub fn sync_events(&self, plan: &SyncPlan) -> Vec<SequencedEvent> {
+        let mut positions: Vec<usize> = plan.links.iter()
+            .filter_map(|range| self.index.positions(self.index.get(&range.slug)?).get(range.from..range.to))
+            .flatten()
+            .copied()
+            .collect();
+        positions.sort_unstable();
+
+        positions.into_iter()
+            .map(|position| {
+                let event = &self.log[position];
+                SequencedEvent {
+                    sequence: position as u64 + 1,
+                    slug: event.slug.clone(),
+                    slug_id: self.index.event_ids[position],
+                    timestamp: event.timestamp,
+                    event_type: event.event_type.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Applies the events of a plan computed by the source's
+    /// [`UrlShortenerService::diff_events`] for this copy, in the order
+    /// given, and returns how many were applied. Everything is checked
+    /// before anything is applied: each link must still be at the plan's
+    /// high-water mark, the events must be exactly the planned ones, and
+    /// each must fit the link's history.
+    ///
+    /// ## Errors
+    ///
+    /// [`SyncError::Discontinuous`] if the copy moved on since the plan was
+    /// computed, e.g. when a plan is applied twice, or the events don't
+    /// match the plan, and [`SyncError::InvalidEvent`] if an event doesn't
+    /// fit its link's history.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, SyncError, Url, UrlShortenerService};
+    ///
+    /// let mut production = UrlShortenerService::new();
+    /// production.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// let mut staging = UrlShortenerService::new();
+    /// let plan = production.diff_events(&staging.version_vector()).unwrap();
+    /// let events = production.sync_events(&plan);
+    /// assert_eq!(staging.apply_sync_plan(&plan, &events), Ok(1));
+    /// assert_eq!(staging.apply_sync_plan(&plan, &events), Err(SyncError::Discontinuous(Slug::from("docs"))));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn apply_sync_plan(&mut self, plan: &SyncPlan, events: &[SequencedEvent]) -> Result<usize, SyncError> {
+        let mut states = HashMap::new();
+        for range in &plan.links {
+            let positions = self.index.get(&range.slug).map_or(&[][..], |id| self.index.positions(id));
+            let planned = events.iter().filter(|event| event.slug == range.slug).count();
+            if positions.len() != range.from || planned != range.to.saturating_sub(range.from) {
+                return Err(SyncError::Discontinuous(range.slug.clone()));
+            }
+
+            let mut state = domain::LinkState::new(range.slug.clone());
+            for &position in positions {
+                state.apply(&self.log[position]);
+            }
+            states.insert(range.slug.clone(), state);
+        }
+
+        let events: Vec<Event> = events.iter()
+            .map(|event| Event {
+                slug: event.slug.clone(),
+                timestamp: event.timestamp,
+                event_type: event.event_type.clone()
+            })
+            .collect();
+        for event in &events {
+            let state = states.get_mut(&event.slug).ok_or_else(|| SyncError::Discontinuous(event.slug.clone()))?;
+            state.check(event).map_err(|error| SyncError::InvalidEvent { slug: event.slug.clone(), error })?;
+            state.apply(event);
+        }
+
+        for event in &events {
+            self.replay_imported(event).expect("the events were checked against their links' histories");
+        }
+
+        Ok(events.len())
+    }
+
+    /// Fingerprints the events at `positions`, in order.
+    fn fingerprint(&self, positions: &[usize]) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for &position in positions {
+            self.log[position].hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Traces how a link's stats came to be: returns the link's event
+    /// recorded closest to `around` and up to `radius` of its events on
+    /// either side, each with the link's [`Stats`] just before and after it.
+    /// The stats are computed by replaying the link's events up to the last
+    /// traced one through a fresh projection, independently of the read
+    /// model, so an event that introduced a wrong figure shows it in its
+    /// delta. A slug without events yields an empty trace.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let trace = service.trace(&Slug::from("docs"), now, 1);
+    /// assert_eq!(trace.events.iter().map(|event| event.sequence).collect::<Vec<_>>(), [1, 2]);
+    /// assert_eq!(trace.events[1].after.as_ref().map(|stats| stats.redirects), Some(1));
+    /// assert!(trace.to_string().contains("#2 at 1000.000s"));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn trace(&self, slug: &Slug, around: SystemTime, radius: usize) -> TraceReport {
+        let positions = self.index.get(slug).map_or(&[][..], |id| self.index.positions(id));
+        let distance = |position: &usize| {
+            let timestamp = self.log[*position].timestamp;
+            timestamp.duration_since(around).or_else(|_| around.duration_since(timestamp)).unwrap_or_default()
+        };
+        let center = (0..positions.len()).min_by_key(|&index| distance(&positions[index]));
+
+        let mut events = Vec::new();
+        if let Some(center) = center {
+            let window = center.saturating_sub(radius)..=center.saturating_add(radius);
+            let mut replayed = Self::new().with_lookup_leniency(self.leniency);
+            for (index, &position) in positions.iter().enumerate().take(window.end().saturating_add(1)) {
+                let event = &self.log[position];
+                let before = replayed.stats.get(slug).cloned();
+                replayed.project_event(event);
+                if window.contains(&index) {
+                    events.push(TracedEvent {
+                        sequence: position as u64 + 1,
+                        timestamp: event.timestamp,
+                        event_type: event.event_type.clone(),
+                        before,
+                        after: replayed.stats.get(slug).cloned()
+                    });
+                }
+            }
+        }
+
+        TraceReport { slug: slug.clone(), around, events }
+    }
+
+    /// Iterates every event across all links in the order they were
+    /// recorded, borrowing them from the store, so the whole log can be
+    /// read, e.g. into a columnar file, without copying it.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    /// service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b")))?;
+    /// service.handle_redirect(Slug::from("a"))?;
+    ///
+    /// let stream: Vec<_> = service.stream_all_events().map(|event| (event.sequence, &event.slug.0[..])).collect();
+    /// assert_eq!(stream, [(1, "a"), (2, "b"), (3, "a")]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn stream_all_events(&self) -> impl Iterator<Item = EventView<'_>> + '_ {
+        self.log.iter()
+            .zip(&self.index.event_ids)
+            .zip(1..)
+            .map(|((event, &slug_id), sequence)| EventView {
+                sequence,
+                slug: &event.slug,
+                slug_id,
+                timestamp: event.timestamp,
+                event_type: &event.event_type
+            })
+    }
+
+    /// Iterates the events of one slug in the order they were recorded,
+    /// including those of earlier links deleted under the same slug, with
+    /// their service-wide sequence numbers. Empty if the slug never had a
+    /// link.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    /// service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b")))?;
+    /// service.handle_redirect(Slug::from("a"))?;
+    ///
+    /// let sequences: Vec<u64> = service.stream_events(&Slug::from("a")).map(|event| event.sequence).collect();
+    /// assert_eq!(sequences, [1, 3]);
+    /// assert_eq!(service.stream_events(&Slug::from("missing")).count(), 0);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn stream_events(&self, slug: &Slug) -> impl Iterator<Item = EventView<'_>> + '_ {
+        let id = self.index.get(slug);
+        let positions = id.map_or(&[][..], |id| self.index.positions(id));
+
+        positions.iter().map(move |&position| {
+            let event = &self.log[position];
+            EventView {
+                sequence: position as u64 + 1,
+                slug: &event.slug,
+                slug_id: self.index.event_ids[position],
+                timestamp: event.timestamp,
+                event_type: &event.event_type
+            }
+        })
+    }
+
+    /// Calls `f` with consecutive chunks of at most `chunk_size` events of
+    /// [`UrlShortenerService::stream_all_events`], e.g. to write one batch
+    /// per chunk. Only one chunk of views is held at a time; a `chunk_size`
+    /// of 0 is treated as 1.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// for slug in ["a", "b", "c"] {
+    ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+    /// }
+    ///
+    /// let mut sizes = Vec::new();
+    /// service.for_each_event_chunk(2, |chunk| sizes.push(chunk.len()));
+    /// assert_eq!(sizes, [2, 1]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn for_each_event_chunk(&self, chunk_size: usize, mut f: impl FnMut(&[EventView<'_>])) {
+        let chunk_size = chunk_size.max(1);
+        let mut chunk = Vec::with_capacity(chunk_size.min(self.log.len()));
+
+        for event in self.stream_all_events() {
+            chunk.push(event);
+            if chunk.len() == chunk_size {
+                f(&chunk);
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            f(&chunk);
+        }
+    }
+
+    /// Returns the number of links and their redirects, maintained as events
+    /// are recorded rather than summed per call.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Totals, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a")))?;
+    /// service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b")))?;
+    /// service.handle_redirect(Slug::from("a"))?;
+    /// assert!(service.handle_redirect(Slug::from("missing")).is_err());
+    ///
+    /// assert_eq!(service.totals(), Totals { links: 2, redirects: 1 });
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn totals(&self) -> Totals {
+        self.totals
+    }
+
+    /// Returns the most redirected links, or `None` if the leaderboard is not
+    /// enabled.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_leaderboard(1);
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("idle")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// assert_eq!(service.leaderboard().map(|board| board.entries), Some(vec![(Slug::from("docs"), 1)]));
+    /// assert!(UrlShortenerService::new().leaderboard().is_none());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn leaderboard(&self) -> Option<Leaderboard> {
+        (self.leaderboard_size > 0).then(|| self.leaderboard.clone())
+    }
+
+    /// Adds a slug to the folded lookup index. When several slugs fold to the
+    /// same key, the lexicographically smallest one wins, so the index does
+    /// not depend on creation order and can be rebuilt identically.
+    fn index_folded_slug(index: &mut HashMap<String, Slug>, slug: &Slug, leniency: &LookupLeniency) {
+        if let Some(key) = domain::fold_slug(&slug.0, leniency) {
+            let canonical = index.entry(key).or_insert_with(|| slug.clone());
+            if slug.0 < canonical.0 {
+                *canonical = slug.clone();
+            }
+        }
+    }
+
+    fn index_url(&mut self, url: &Url, slug: &Slug) {
+        self.slugs_by_url.entry(url.0.clone()).or_default().insert(slug.clone());
+    }
+
+    fn unindex_url(&mut self, url: &Url, slug: &Slug) {
+        if let Some(slugs) = self.slugs_by_url.get_mut(&url.0) {
+            slugs.remove(slug);
+            if slugs.is_empty() {
+                self.slugs_by_url.remove(&url.0);
+            }
+        }
+    }
+
+    /// Removes a deleted slug from the folded lookup index, handing its key
+    /// to the next smallest slug that folds to it, if any.
+    fn unindex_folded_slug(&mut self, slug: &Slug) {
+        let Some(key) = domain::fold_slug(&slug.0, &self.leniency) else {
+            return;
+        };
+
+        if self.folded_slugs.get(&key) == Some(slug) {
+            self.folded_slugs.remove(&key);
+            for link_stats in self.stats.values() {
+                if domain::fold_slug(&link_stats.link.slug.0, &self.leniency).as_ref() == Some(&key) {
+                    Self::index_folded_slug(&mut self.folded_slugs, &link_stats.link.slug, &self.leniency);
+                }
+            }
+        }
+    }
+
+    fn build_folded_index(stats: &BTreeMap<Slug, Stats>, leniency: &LookupLeniency) -> HashMap<String, Slug> {
+        let mut index = HashMap::new();
+        for link_stats in stats.values() {
+            Self::index_folded_slug(&mut index, &link_stats.link.slug, leniency);
+        }
+        index
+    }
+
+    /// Cross-checks the read model and lookup index against the event store,
+    /// listing every discrepancy. In [`RepairMode::Fix`] the read model and
+    /// index are also replaced by their replayed versions; fixing a consistent
+    /// service changes nothing, so repeated fixes are harmless.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{RepairMode, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    ///
+    /// let report = service.repair(RepairMode::Report);
+    /// assert!(report.is_consistent());
+    /// assert!(report.mismatched_stats.is_empty());
+    /// assert_eq!(service.repair(RepairMode::Fix), report);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn repair(&mut self, mode: RepairMode) -> RepairReport {
+        let mut replayed = Self::new()
+            .with_lookup_leniency(self.leniency)
+            .with_recent_clicks(self.recent_clicks.capacity);
+        for event in &self.log {
+            replayed.project_event(event);
+        }
+        let expected_stats = replayed.stats;
+        let expected_index = replayed.folded_slugs;
+
+        let mut report = RepairReport::default();
+        for (slug, stats) in &self.stats {
+            match expected_stats.get(slug) {
+                None => report.orphan_stats.push(slug.clone()),
+                Some(expected) if expected != stats => {
+                    report.mismatched_stats.push(slug.clone())
+                }
+                Some(_) => {}
+            }
+        }
+        for slug in expected_stats.keys() {
+            if !self.stats.contains_key(slug) {
+                report.missing_stats.push(slug.clone());
+            }
+        }
+        for (key, slug) in &self.folded_slugs {
+            if expected_index.get(key) != Some(slug) {
+                report.dangling_index_entries.push(key.clone());
+            }
+        }
+        for (key, slug) in &expected_index {
+            if self.folded_slugs.get(key) != Some(slug) {
+                report.missing_index_entries.push(key.clone());
+            }
+        }
+        report.mismatched_serving_hints = Self::diff_keys(&self.serving_hints, &replayed.serving_hints);
+        report.mismatched_dead_links = Self::diff_keys(&self.link_health, &replayed.link_health);
+        report.mismatched_totals = self.totals != replayed.totals;
+        report.mismatched_expiries = Self::diff_keys(&self.expiries, &replayed.expiries);
+        report.mismatched_redirect_limits = Self::diff_keys(&self.redirect_limits, &replayed.redirect_limits);
+        report.mismatched_consumed_flags = self.consumed_slugs
+            .symmetric_difference(&replayed.consumed_slugs)
+            .cloned()
+            .collect();
+        report.mismatched_url_index_entries = Self::diff_keys(&self.slugs_by_url, &replayed.slugs_by_url)
+            .into_iter()
+            .map(Url)
+            .collect();
+        report.mismatched_permanent_flags = self.permanent_slugs
+            .symmetric_difference(&replayed.permanent_slugs)
+            .cloned()
+            .collect();
+        report.sort();
+
+        if mode == RepairMode::Fix {
+            self.stats = expected_stats;
+            self.folded_slugs = expected_index;
+            self.serving_hints = replayed.serving_hints;
+            self.link_health = replayed.link_health;
+            self.permanent_slugs = replayed.permanent_slugs;
+            self.expiries = replayed.expiries;
+            self.redirect_limits = replayed.redirect_limits;
+            self.consumed_slugs = replayed.consumed_slugs;
+            self.slugs_by_url = replayed.slugs_by_url;
+            self.creation_order = replayed.creation_order;
+            self.seeded = replayed.seeded;
+            self.stats_grants = replayed.stats_grants;
+            self.recent_clicks = replayed.recent_clicks;
+            self.expiry_order = replayed.expiry_order;
+            self.totals = replayed.totals;
+            self.index.clear_snapshots();
+            self.reconcile_leaderboards();
+        }
+
+        report
+    }
+
+    /// Rebuilds every read model from the event store through the same
+    /// projection used for live updates, e.g. after they got out of sync or
+    /// a projection bug was fixed. Shorthand for
+    /// [`UrlShortenerService::repair`] in [`RepairMode::Fix`] when the
+    /// discrepancies don't matter. Outcome counters aren't derived from
+    /// events and are kept.
+    ///
+    /// Events are replayed from the global log in the order they were
+    /// recorded, not slug by slug, so read models spanning several links,
+    /// such as the URL index or a slug reused after deletion, come out as
+    /// they were built live.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("old")))?;
+    /// service.handle_delete_short_link(Slug::from("old"))?;
+    /// let before = service.get_stats(Slug::from("docs"))?;
+    ///
+    /// service.rebuild_read_model();
+    /// assert_eq!(service.get_stats(Slug::from("docs"))?, before);
+    /// assert_eq!(service.get_stats(Slug::from("old")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    #[doc(alias = "rebuild_projection")]
+    pub fn rebuild_read_model(&mut self) {
+        self.repair(RepairMode::Fix);
+    }
+
+    /// Dates the events logged before timestamps existed, which read as
+    /// [`SystemTime::UNIX_EPOCH`], according to `strategy`. The events are
+    /// rewritten in the log, so exports and saved state carry the new
+    /// times, and every read model is rebuilt from them as by
+    /// [`UrlShortenerService::rebuild_read_model`]. Each affected link then
+    /// gets an [`EventType::TimestampsBackfilled`] event, timestamped by
+    /// the service's clock, as an audit trail. Events that already have a
+    /// timestamp are left alone, so running it again changes nothing.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{BackfillStrategy, FixedClock, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(SystemTime::UNIX_EPOCH));
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// let then = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    /// let report = service.backfill_timestamps(BackfillStrategy::ConstantAt(then));
+    /// assert_eq!((report.backfilled, report.slugs), (1, vec![Slug::from("docs")]));
+    /// assert_eq!(service.get_stats(Slug::from("docs"))?.created_at, then);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn backfill_timestamps(&mut self, strategy: BackfillStrategy) -> BackfillReport {
+        let legacy: Vec<usize> = (0..self.log.len())
+            .filter(|&position| self.log[position].timestamp == SystemTime::UNIX_EPOCH)
+            .collect();
+
+        let mut backfilled: BTreeMap<Slug, u64> = BTreeMap::new();
+        for (index, &position) in legacy.iter().enumerate() {
+            let timestamp = match strategy {
+                BackfillStrategy::ConstantAt(at) => Some(at),
+                BackfillStrategy::SpreadEvenly { from, to } => {
+                    let span = to.duration_since(from).unwrap_or_default();
+                    let share = index as f64 / legacy.len().saturating_sub(1).max(1) as f64;
+                    Some(from + span.mul_f64(share))
+                }
+                BackfillStrategy::CopyFromNeighbor => {
+                    let dated = |event: &&Event| event.timestamp != SystemTime::UNIX_EPOCH;
+                    self.log[position..].iter().find(dated)
+                        .or_else(|| self.log[..position].iter().rev().find(dated))
+                        .map(|event| event.timestamp)
+                }
+            };
+
+            if let Some(timestamp) = timestamp.filter(|&timestamp| timestamp != SystemTime::UNIX_EPOCH) {
+                self.log[position].timestamp = timestamp;
+                *backfilled.entry(self.log[position].slug.clone()).or_default() += 1;
+            }
+        }
+
+        let report = BackfillReport {
+            backfilled: backfilled.values().sum::<u64>() as usize,
+            slugs: backfilled.keys().cloned().collect()
+        };
+        if backfilled.is_empty() {
+            return report;
+        }
+
+        self.rebuild_read_model();
+        for (slug, events) in backfilled {
+            let mut aggregate = ShortLinkAggregate::new(self);
+            aggregate.rehydrate_by_slug(&slug);
+            aggregate.record_backfill(events);
+            self.after_command();
+        }
+
+        report
+    }
+
+    fn create_short_link(
+        &mut self,
+        url: &Url,
+        slug: Option<Slug>,
+        expires_at: Option<SystemTime>
+    ) -> Result<ShortLink, ShortenerError> {
+        match slug {
+            Some(slug) => {
+                let mut aggregate = ShortLinkAggregate::new(self);
+                aggregate.rehydrate_by_slug(&slug);
+                aggregate.create_short_link(url, expires_at)
+            }
+            None => self.create_with_generated_slug(url, expires_at)
+        }
+    }
+
+    /// Creates a link under a generated slug, retrying with a new candidate
+    /// while the generated one is taken or spells a filtered word. Candidates go through the same
+    /// aggregate check as slugs given by the caller, so an existing link is
+    /// never overwritten.
+    fn create_with_generated_slug(
+        &mut self,
+        url: &Url,
+        expires_at: Option<SystemTime>
+    ) -> Result<ShortLink, ShortenerError> {
+        for _ in 0..self.slug_generation_attempts {
+            let slug = self.slug_generator.generate(self.clock.now());
+            if self.word_filter.as_ref().is_some_and(|filter| filter.rejects(&slug)) {
+                continue;
+            }
+
+            let mut aggregate = ShortLinkAggregate::new(self);
+            aggregate.rehydrate_by_slug(&slug);
+
+            match aggregate.create_short_link(url, expires_at) {
+                Err(ShortenerError::SlugAlreadyInUse) => continue,
+                result => return result
+            }
+        }
+
+        Err(ShortenerError::SlugGenerationFailed)
+    }
+
+    /// Lists keys whose entries differ between two read models, sorted.
+    /// Signs the creation sequence of the last link of a page.
+    fn cursor_signature(&self, version: u32, sequence: u64) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (self.cursor_key, version, sequence).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn issue_cursor(&self, sequence: u64) -> String {
+        format!("v{CURSOR_VERSION}.{sequence:x}.{:016x}", self.cursor_signature(CURSOR_VERSION, sequence))
+    }
+
+    /// Returns the creation sequence a cursor points at.
+    fn parse_cursor(&self, cursor: &str) -> Result<u64, CursorError> {
+        let mut parts = cursor.split('.');
+        let (Some(version), Some(sequence), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(CursorError::Malformed);
+        };
+
+        let version = version.strip_prefix('v')
+            .and_then(|version| version.parse::<u32>().ok())
+            .ok_or(CursorError::Malformed)?;
+        if version != CURSOR_VERSION {
+            return Err(CursorError::UnsupportedVersion(version));
+        }
+        let sequence = u64::from_str_radix(sequence, 16).map_err(|_| CursorError::Malformed)?;
+        let signature = u64::from_str_radix(signature, 16).map_err(|_| CursorError::Malformed)?;
+
+        if signature == self.cursor_signature(version, sequence) {
+            Ok(sequence)
+        } else {
+            Err(CursorError::Tampered)
+        }
+    }
+
+    fn diff_keys<K: Clone + Hash + Ord, V: PartialEq>(live: &HashMap<K, V>, replayed: &HashMap<K, V>) -> Vec<K> {
+        let mut keys: Vec<K> = live.keys().chain(replayed.keys())
+            .filter(|key| live.get(*key) != replayed.get(*key))
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+
+        keys
+    }
+
+    /// Maps a requested slug to the stored one, trying the exact form first
+    /// and then the normalizations enabled by [`LookupLeniency`] and
+    /// [`SlugDisplayPolicy`].
+    fn resolve_slug(&self, slug: &Slug) -> Option<(&Stats, LookupNormalization)> {
+        if let Some(stats) = self.stats.get(slug) {
+            return Some((stats, LookupNormalization::Exact));
+        }
+
+        let mut requested = slug.0.as_str();
+        if self.leniency.strip_trailing_slash {
+            if let Some(stripped) = requested.strip_suffix('/') {
+                if let Some(stats) = self.stats.get(stripped) {
+                    return Some((stats, LookupNormalization::TrailingSlash));
+                }
+                requested = stripped;
+            }
+        }
+
+        if let Some(stripped) = self.display_policy.strip_display(requested) {
+            if let Some(stats) = self.stats.get(stripped) {
+                return Some((stats, LookupNormalization::DisplayForm));
+            }
+            if self.display_policy.uppercase_display {
+                if let Some(stats) = self.stats.get(stripped.to_lowercase().as_str()) {
+                    return Some((stats, LookupNormalization::DisplayForm));
+                }
+            }
+            requested = stripped;
+        }
+
+        let key = domain::fold_slug(requested, &self.leniency)?;
+        let canonical = self.folded_slugs.get(&key)?;
+        self.stats.get(canonical)
+            .map(|stats| (stats, LookupNormalization::Folded))
+    }
+
+    /// Returns the destination URL of a slug without cloning it, so a serving
+    /// layer can answer a redirect under a shared borrow. Fails like
+    /// [`commands::CommandHandler::handle_redirect`] would, but records
+    /// nothing; the click itself is recorded separately with
+    /// [`UrlShortenerService::record_click`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("paused")))?;
+    /// service.handle_set_link_enabled(Slug::from("paused"), false)?;
+    ///
+    /// assert_eq!(service.resolve_url_borrowed(&Slug::from("docs")), Ok("https://example.com"));
+    /// assert_eq!(service.get_stats(Slug::from("docs"))?.redirects, 0);
+    /// assert_eq!(service.resolve_url_borrowed(&Slug::from("paused")), Err(ShortenerError::LinkDisabled));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn resolve_url_borrowed(&self, slug: &Slug) -> Result<&str, ShortenerError> {
+        let (stats, _) = self.resolve_slug(slug).ok_or(ShortenerError::SlugNotFound)?;
+        self.redirect_gate(stats).check(self.clock.now())?;
+
+        Ok(stats.link.url.0.as_str())
+    }
+
+    /// Gathers the redirect gates of an existing link from the read models.
+    fn redirect_gate(&self, stats: &Stats) -> domain::RedirectGate {
+        let slug = &stats.link.slug;
+        let health = self.link_health.get(slug);
+
+        domain::RedirectGate {
+            consumed: self.consumed_slugs.contains(slug),
+            disabled: !stats.enabled,
+            expires_at: self.expiries.get(slug).copied(),
+            redirects: stats.redirects,
+            redirect_limit: self.redirect_limits.get(slug).copied(),
+            dead: health.is_some_and(|health| health.dead.is_some()),
+            dead_link_policy: health.and_then(|health| health.policy).unwrap_or(self.dead_link_policy)
+        }
+    }
+
+    /// Returns the [`ServingHints`] of a slug, or the defaults if none were
+    /// set. Lookup leniency applies as for redirects.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ServingHints, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// assert_eq!(service.serving_hints(&Slug::from("docs"))?, ServingHints::default());
+    ///
+    /// let hints = ServingHints { cache_control: Some("max-age=60".to_string()), ..ServingHints::default() };
+    /// service.handle_set_serving_hints(Slug::from("docs"), Some(hints.clone()))?;
+    /// assert_eq!(service.serving_hints(&Slug::from("docs"))?, hints);
+    /// assert_eq!(service.serving_hints(&Slug::from("missing")), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn serving_hints(&self, slug: &Slug) -> Result<ServingHints, ShortenerError> {
+        let (stats, _) = self.resolve_slug(slug).ok_or(ShortenerError::SlugNotFound)?;
+        let hints = self.serving_hints.get(&stats.link.slug).cloned().unwrap_or_default();
+
+        Ok(hints)
+    }
+
+    /// Lists live links whose stored URL fails the given validator, sorted by
+    /// slug. Nothing is modified, so a stricter experimental validator can be
+    /// tried without affecting redirects.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{InvalidUrlReason, ShortenerError, Slug, Url, UrlShortenerService, UrlValidator};
+    ///
+    /// /// Only allows HTTPS.
+    /// struct HttpsOnly;
+    ///
+    /// impl UrlValidator for HttpsOnly {
+    ///     fn validate(&self, url: &Url) -> Result<(), InvalidUrlReason> {
+    ///         url.0.starts_with("https://").then_some(()).ok_or(InvalidUrlReason::InvalidScheme)
+    ///     }
+    /// }
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("safe")))?;
+    /// service.handle_create_short_link(Url::from("http://example.com"), Some(Slug::from("plain")))?;
+    ///
+    /// let offenders = service.audit_destinations(&HttpsOnly);
+    /// assert_eq!(offenders.len(), 1);
+    /// assert_eq!((&offenders[0].0.slug, offenders[0].1), (&Slug::from("plain"), InvalidUrlReason::InvalidScheme));
+    /// assert!(service.handle_redirect(Slug::from("plain")).is_ok());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn audit_destinations(&self, validator: &dyn UrlValidator) -> Vec<(ShortLink, InvalidUrlReason)> {
+        let mut offenders = Vec::new();
+        self.audit_destinations_with(validator, |link, reason| {
+            offenders.push((link.clone(), reason));
+        });
+        offenders.sort_by(|(a, _), (b, _)| a.slug.cmp(&b.slug));
+
+        offenders
+    }
+
+    /// Streaming variant of [`UrlShortenerService::audit_destinations`]: calls
+    /// `on_offender` for every failing link, in no particular order, without
+    /// collecting the results.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{
+    ///     DefaultUrlValidator, InvalidUrlReason, ShortenerError, Slug, Url, UrlShortenerService, UrlValidator,
+    /// };
+    ///
+    /// /// Blocks one host on top of the default rules.
+    /// struct Blocklist;
+    ///
+    /// impl UrlValidator for Blocklist {
+    ///     fn validate(&self, url: &Url) -> Result<(), InvalidUrlReason> {
+    ///         DefaultUrlValidator.validate(url)?;
+    ///         if url.0.contains("evil.com") { Err(InvalidUrlReason::Blocked) } else { Ok(()) }
+    ///     }
+    /// }
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://evil.com"), Some(Slug::from("bad")))?;
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("good")))?;
+    ///
+    /// let mut blocked = Vec::new();
+    /// service.audit_destinations_with(&Blocklist, |link, reason| blocked.push((link.slug.clone(), reason)));
+    /// assert_eq!(blocked, [(Slug::from("bad"), InvalidUrlReason::Blocked)]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn audit_destinations_with(
+        &self,
+        validator: &dyn UrlValidator,
+        mut on_offender: impl FnMut(&ShortLink, InvalidUrlReason)
+    ) {
+        for stats in self.stats.values() {
+            if let Err(reason) = validator.validate(&stats.link.url) {
+                on_offender(&stats.link, reason);
+            }
+        }
+    }
+
+    /// Lists links marked dead with the evidence given, sorted by slug.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// assert!(service.dead_links().is_empty());
+    /// for slug in ["b", "a"] {
+    ///     service.handle_create_short_link(Url::from("https://example.com/gone"), Some(Slug::from(slug)))?;
+    ///     service.handle_mark_dead(Slug::from(slug), format!("404 on {slug}"))?;
+    /// }
+    ///
+    /// let dead = [(Slug::from("a"), "404 on a".to_string()), (Slug::from("b"), "404 on b".to_string())];
+    /// assert_eq!(service.dead_links(), dead);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn dead_links(&self) -> Vec<(Slug, String)> {
+        let mut dead: Vec<(Slug, String)> = self.link_health.iter()
+            .filter_map(|(slug, health)| {
+                health.dead.as_ref().map(|evidence| (slug.clone(), evidence.clone()))
+            })
+            .collect();
+        dead.sort();
+
+        dead
+    }
+
+    /// Lists the slugs of all links pointing at `url`, sorted. URLs are
+    /// compared in the normalized form links are stored in.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com/page"), Some(Slug::from("b")))?;
+    /// service.handle_create_short_link(Url::from("https://example.com/page"), Some(Slug::from("a")))?;
+    ///
+    /// let slugs = service.find_slugs_for_url(&Url::from("https://example.com/page"));
+    /// assert_eq!(slugs, [Slug::from("a"), Slug::from("b")]);
+    /// assert!(service.find_slugs_for_url(&Url::from("https://example.com/other")).is_empty());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn find_slugs_for_url(&self, url: &Url) -> Vec<Slug> {
+        self.slugs_by_url.get(&ServiceUrlValidator::default().normalize(url).0)
+            .map(|slugs| slugs.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns when a short link expires, or `None` if it never does.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// let deadline = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+    /// let url = Url::from("https://example.com");
+    /// service.handle_create_short_link_with_expiry(url, Some(Slug::from("sale")), deadline)?;
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// assert_eq!(service.expires_at(&Slug::from("sale")), Some(deadline));
+    /// assert_eq!(service.expires_at(&Slug::from("docs")), None);
+    /// assert_eq!(service.expires_at(&Slug::from("missing")), None);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn expires_at(&self, slug: &Slug) -> Option<SystemTime> {
+        self.expiries.get(slug).copied()
+    }
+
+    /// Returns when a short link expires together with the time remaining
+    /// by the service's clock, or `None` if it never expires or doesn't
+    /// exist. The remaining time is zero from the moment of expiry on.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{FixedClock, LinkExpiry, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    /// let mut service = UrlShortenerService::new().with_clock(FixedClock(now));
+    /// service.handle_create_with_ttl(Url::from("https://example.com"), Some(Slug::from("sale")), 60)?;
+    ///
+    /// let expiry = LinkExpiry { expires_at: now + Duration::from_secs(60), remaining: Duration::from_secs(60) };
+    /// assert_eq!(service.expiry(&Slug::from("sale")), Some(expiry));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn expiry(&self, slug: &Slug) -> Option<LinkExpiry> {
+        let expires_at = self.expires_at(slug)?;
+        let remaining = expires_at.duration_since(self.clock.now()).unwrap_or_default();
+
+        Some(LinkExpiry { expires_at, remaining })
+    }
+
+    /// Returns the links that are still live at `now` but expire within
+    /// `window` of it, soonest first, together with their expiry. Backed by
+    /// an expiry-ordered index, so only the links returned are visited.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, SystemTime};
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortLink, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+    /// let day = Duration::from_secs(24 * 3_600);
+    /// for (slug, days) in [("month", 30), ("tomorrow", 1), ("week", 7)] {
+    ///     service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from(slug)))?;
+    ///     service.handle_set_expiry(Slug::from(slug), Some(now + days * day))?;
+    /// }
+    ///
+    /// let expiring: Vec<_> = service.expiring_within(7 * day, now).into_iter().map(|(link, _)| link.slug).collect();
+    /// assert_eq!(expiring, [Slug::from("tomorrow"), Slug::from("week")]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn expiring_within(&self, window: Duration, now: SystemTime) -> Vec<(ShortLink, SystemTime)> {
+        let until = now.checked_add(window);
+
+        self.expiry_order.links
+            .range((now, Slug(String::new()))..)
+            .skip_while(|(expires_at, _)| *expires_at <= now)
+            .take_while(|(expires_at, _)| until.is_none_or(|until| *expires_at <= until))
+            .filter_map(|(expires_at, slug)| Some((self.stats.get(slug)?.link.clone(), *expires_at)))
+            .collect()
+    }
+
+    /// Announces the links expiring within the lead time of each notifier
+    /// configured with [`UrlShortenerService::with_expiry_notifier`], as
+    /// told by the service's clock, and returns how many notices were sent.
+    /// A link already announced for a lead time since its expiry was last
+    /// set is skipped. Meant to be run periodically.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::UrlShortenerService;
+    ///
+    /// assert_eq!(UrlShortenerService::new().sweep_expiry_notices(), 0);
+    /// ```
+    pub fn sweep_expiry_notices(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut notifiers = std::mem::take(&mut self.expiry_notifiers);
+        let mut sent = 0;
+
+        for notifier in &mut notifiers {
+            for (link, expires_at) in self.expiring_within(notifier.lead, now) {
+                if self.expiry_order.notified(&link.slug, notifier.lead) {
+                    continue;
+                }
+
+                let mut aggregate = ShortLinkAggregate::new(self);
+                aggregate.rehydrate_by_slug(&link.slug);
+                let recorded = aggregate.record_expiry_notice(notifier.lead);
+                self.after_command();
+
+                if recorded.is_ok() {
+                    (notifier.notify)(&link, expires_at);
+                    sent += 1;
+                }
+            }
+        }
+
+        self.expiry_notifiers = notifiers;
+        sent
+    }
+
+    /// Returns whether a short link was made permanent.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("flyer")))?;
+    /// assert!(!service.is_permanent(&Slug::from("flyer")));
+    ///
+    /// service.handle_make_permanent(Slug::from("flyer"))?;
+    /// assert!(service.is_permanent(&Slug::from("flyer")));
+    /// assert!(!service.is_permanent(&Slug::from("missing")));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn is_permanent(&self, slug: &Slug) -> bool {
+        self.permanent_slugs.contains(slug)
+    }
+
+    /// Returns the evidence and effective [`DeadLinkPolicy`] of a link marked
+    /// dead, or `None` if it is alive.
+    fn dead_link(&self, slug: &Slug) -> Option<(&str, DeadLinkPolicy)> {
+        let health = self.link_health.get(slug)?;
+        let evidence = health.dead.as_deref()?;
+
+        Some((evidence, health.policy.unwrap_or(self.dead_link_policy)))
+    }
+
+    /// Returns how redirect attempts on an existing slug ended.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::{RedirectOutcome, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("promo")))?;
+    /// service.handle_redirect(Slug::from("promo"))?;
+    /// service.handle_set_link_enabled(Slug::from("promo"), false)?;
+    /// assert!(service.handle_redirect(Slug::from("promo")).is_err());
+    ///
+    /// let outcomes = service.get_outcome_stats(&Slug::from("promo"))?;
+    /// assert_eq!((outcomes.get(RedirectOutcome::Success), outcomes.get(RedirectOutcome::Disabled)), (1, 1));
+    /// assert!(service.get_outcome_stats(&Slug::from("missing")).is_err());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_outcome_stats(&self, slug: &Slug) -> Result<OutcomeStats, ShortenerError> {
+        let (stats, _) = self.resolve_slug(slug).ok_or(ShortenerError::SlugNotFound)?;
+        let outcomes = self.outcomes.get(&stats.link.slug).cloned().unwrap_or_default();
+
+        Ok(outcomes)
+    }
+
+    /// Returns how all redirect attempts ended, including attempts on slugs
+    /// that don't exist.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{RedirectOutcome, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    /// service.handle_redirect(Slug::from("docs"))?;
+    /// assert!(service.handle_redirect(Slug::from("missing")).is_err());
+    ///
+    /// let outcomes = service.get_global_outcome_stats();
+    /// assert_eq!((outcomes.get(RedirectOutcome::Success), outcomes.get(RedirectOutcome::NotFound)), (1, 1));
+    /// assert_eq!(outcomes.total(), 2);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_global_outcome_stats(&self) -> OutcomeStats {
+        self.global_outcomes.clone()
+    }
+
+    fn record_outcome(&mut self, slug: &Slug, result: &Result<ShortLink, ShortenerError>) {
+        let outcome = match result {
+            Ok(_) => RedirectOutcome::Success,
+            Err(ShortenerError::LinkDead) => RedirectOutcome::Dead,
+            Err(ShortenerError::LinkExpired) => RedirectOutcome::Expired,
+            Err(ShortenerError::LinkDisabled) => RedirectOutcome::Disabled,
+            Err(ShortenerError::LinkExhausted) => RedirectOutcome::Exhausted,
+            Err(_) => RedirectOutcome::NotFound
+        };
+
+        self.global_outcomes.record(outcome);
+        if self.stats.contains_key(slug) {
+            self.outcomes.entry(slug.clone()).or_default().record(outcome);
+        }
+    }
+
+    /// Resolves a redirect, records its outcome and, on success, the click,
+    /// all within one `&mut` borrow, and tells the serving layer what to
+    /// respond with.
+    ///
+    /// Gates are checked in this order, the first failing one deciding the
+    /// response:
+    ///
+    /// 1. the slug must map to a short link, which a one-time link stops
+    ///    doing after its redirect (`404`);
+    /// 2. the link must be enabled (`404`, as if it didn't exist);
+    /// 3. the link must not have expired (`410`);
+    /// 4. the link must not have reached its redirect limit (`410`);
+    /// 5. a link marked dead is refused (`410`) or served behind an
+    ///    interstitial, according to its [`DeadLinkPolicy`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{RedirectDecision, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// let RedirectDecision::Redirect { link, hints } = service.decide_redirect(Slug::from("docs")) else {
+    ///     panic!("a live link redirects");
+    /// };
+    /// assert_eq!((link.url, hints.status.status_code()), (Url::from("https://example.com"), 302));
+    ///
+    /// let missing = service.decide_redirect(Slug::from("missing"));
+    /// assert_eq!(missing, RedirectDecision::Error { error: ShortenerError::SlugNotFound, status: 404 });
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn decide_redirect(&mut self, slug: Slug) -> RedirectDecision {
+        match commands::CommandHandler::handle_redirect(self, slug) {
+            Ok(link) => {
+                let hints = self.serving_hints.get(&link.slug).cloned().unwrap_or_default();
+                match self.dead_link(&link.slug) {
+                    Some((evidence, DeadLinkPolicy::ServeWithInterstitial)) => {
+                        let evidence = evidence.to_string();
+                        RedirectDecision::Interstitial { link, hints, evidence }
+                    }
+                    _ => RedirectDecision::Redirect { link, hints }
+                }
+            }
+            Err(error) => {
+                let status = match error {
+                    ShortenerError::SlugNotFound | ShortenerError::LinkDisabled => 404,
+                    ShortenerError::LinkDead | ShortenerError::LinkExpired | ShortenerError::LinkExhausted => 410,
+                    _ => 400
+                };
+                RedirectDecision::Error { error, status }
+            }
+        }
+    }
+
+    /// Records a click on a slug previously resolved with
+    /// [`UrlShortenerService::resolve_url_borrowed`], attributing it to the
+    /// destination in `ctx` that was actually served, even if the link was
+    /// pointed elsewhere in between.
+    ///
+    /// The gates were checked when the redirect was resolved and are not
+    /// checked again: a link disabled, expired or exhausted since then still
+    /// gets the click, a one-time link is consumed by its first recorded
+    /// click and keeps any later ones that were served concurrently, and a
+    /// redirect limit reached in between may be exceeded. The click is
+    /// timestamped when it is recorded, and counts as a successful redirect.
+    ///
+    /// ## Errors
+    ///
+    /// Fails with [`ShortenerError::SlugNotFound`] if the link no longer
+    /// exists by the time the click is recorded.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::{CommandHandler, ExtendedCommandHandler};
+    /// use url_shortener::queries::QueryHandler;
+    /// use url_shortener::{RedirectContext, ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// let slug = Slug::from("docs");
+    /// service.handle_create_one_time(Url::from("https://example.com"), Some(slug.clone()))?;
+    ///
+    /// let ctx = RedirectContext::new(service.resolve_url_borrowed(&slug)?);
+    /// service.record_click(&slug, ctx.clone())?;
+    /// assert_eq!(service.resolve_url_borrowed(&slug), Err(ShortenerError::SlugNotFound));
+    ///
+    /// // Another client was served before the first click was recorded.
+    /// service.record_click(&slug, ctx.clone())?;
+    /// assert_eq!(service.get_stats(slug.clone())?.redirects, 2);
+    ///
+    /// service.handle_delete_short_link(slug.clone())?;
+    /// assert_eq!(service.record_click(&slug, ctx), Err(ShortenerError::SlugNotFound));
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn record_click(&mut self, slug: &Slug, ctx: RedirectContext) -> Result<(), ShortenerError> {
+        let slug = match self.resolve_slug(slug) {
+            Some((stats, _)) => stats.link.slug.clone(),
+            None => slug.clone()
+        };
+
+        let now = self.clock.now();
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.record_served(ctx.served_url, ctx.lookup, now);
+        self.record_outcome(&slug, &result);
+        self.after_command();
+
+        result.map(|_| ())
+    }
+
+    /// Runs every check of [`commands::CommandHandler::handle_create_short_link`]
+    /// without changing any state, collecting all failures instead of stopping
+    /// at the first one.
+    ///
+    /// Links have no owners and creation has no quotas, so unlike creates in
+    /// a multi-tenant deployment this takes no owner; there are no owner
+    /// checks to run yet.
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// assert!(service.validate_create(&Url::from("https://example.org"), Some(&Slug::from("blog"))).is_ok());
+    /// let report = service.validate_create(&Url::from("invalid-url"), Some(&Slug::from("docs")));
+    /// assert_eq!(report.codes(), ["SLUG_IN_USE", "URL_INVALID"]);
+    /// assert!(service.get_events(&Slug::from("blog")).is_err());
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn validate_create(&self, url: &Url, slug: Option<&Slug>) -> ValidationReport {
+        ValidationReport {
+            issues: domain::validate_create(self, url, slug)
+        }
+    }
+
+    /// Returns the [`Stats`] of several links at once, like
+    /// [`queries::ExtendedQueryHandler::get_stats_batch`], with each failure
+    /// summarized by its slug under the configured [`SummaryRedaction`].
+    ///
+    /// ## Examples
+    ///
+    /// ```
+    /// use url_shortener::commands::CommandHandler;
+    /// use url_shortener::{ShortenerError, Slug, Url, UrlShortenerService};
+    ///
+    /// let mut service = UrlShortenerService::new();
+    /// service.handle_create_short_link(Url::from("https://example.com"), Some(Slug::from("docs")))?;
+    ///
+    /// let bulk = service.get_stats_bulk(&[Slug::from("docs"), Slug::from("missing")]);
+    /// assert_eq!(bulk.succeeded().map(|stats| &stats.link.slug).collect::<Vec<_>>(), [&Slug::from("docs")]);
+    /// let failed: Vec<_> = bulk.failed().map(|item| (item.index, &item.error)).collect();
+    /// assert_eq!(failed, [(1, &ShortenerError::SlugNotFound)]);
+    /// # Ok::<(), ShortenerError>(())
+    /// ```
+    pub fn get_stats_bulk(&self, slugs: &[Slug]) -> BulkOutcome<Stats> {
+        let items = slugs.iter()
+            .enumerate()
+            .map(|(index, slug)| {
+                self.stats.get(slug).cloned().ok_or_else(|| ItemError {
+                    index,
+                    input_summary: self.summary_redaction.summarize(&slug.0),
+                    error: ShortenerError::SlugNotFound
+                })
+            })
+            .collect();
+
+        BulkOutcome { items }
+    }
+}
+
+impl Default for UrlShortenerService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+use domain::ShortLinkAggregate as ShortLinkAggregate;
+
+impl commands::CommandHandler for UrlShortenerService {
+    fn handle_create_short_link(
+        &mut self,
+        url: Url,
+        slug: Option<Slug>,
+    ) -> Result<ShortLink, ShortenerError> {
+        let result = self.create_short_link(&url, slug, None);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_redirect(
+        &mut self,
+        slug: Slug,
+    ) -> Result<ShortLink, ShortenerError> {
+        let (slug, lookup) = match self.resolve_slug(&slug) {
+            Some((stats, lookup)) => (stats.link.slug.clone(), lookup),
+            None => (slug, LookupNormalization::Exact)
+        };
+
+        let dead_link_policy = self.dead_link_policy;
+        let now = self.clock.now();
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.redirect(lookup, dead_link_policy, now);
+        self.record_outcome(&slug, &result);
+        self.after_command();
+
+        result
+    }
+}
+
+impl commands::ExtendedCommandHandler for UrlShortenerService {
+    fn handle_create_short_link_with_expiry(
+        &mut self,
+        url: Url,
+        slug: Option<Slug>,
+        expires_at: SystemTime,
+    ) -> Result<ShortLink, ShortenerError> {
+        let result = self.create_short_link(&url, slug, Some(expires_at));
+        self.after_command();
+
+        result
+    }
+
+    fn handle_create_with_ttl(
+        &mut self,
+        url: Url,
+        slug: Option<Slug>,
+        ttl_secs: u64,
+    ) -> Result<ShortLink, ShortenerError> {
+        let expires_at = self.clock.now().checked_add(Duration::from_secs(ttl_secs));
+        let result = self.create_short_link(&url, slug, expires_at);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_create_one_time(
+        &mut self,
+        url: Url,
+        slug: Option<Slug>,
+    ) -> Result<ShortLink, ShortenerError> {
+        let result = self.create_short_link(&url, slug, None).and_then(|link| {
+            let mut aggregate = ShortLinkAggregate::new(self);
+            aggregate.rehydrate_by_slug(&link.slug);
+            aggregate.make_one_time()?;
+
+            Ok(link)
+        });
+        self.after_command();
+
+        result
+    }
+
+    fn handle_create_with_limit(
+        &mut self,
+        url: Url,
+        slug: Option<Slug>,
+        max_redirects: u64,
+    ) -> Result<ShortLink, ShortenerError> {
+        let result = self.create_short_link(&url, slug, None).and_then(|link| {
+            let mut aggregate = ShortLinkAggregate::new(self);
+            aggregate.rehydrate_by_slug(&link.slug);
+            aggregate.set_redirect_limit(max_redirects)?;
+
+            Ok(link)
+        });
+        self.after_command();
+
+        result
+    }
+
+    fn handle_get_or_create(
+        &mut self,
+        url: Url,
+    ) -> Result<ShortLink, ShortenerError> {
+        let now = self.clock.now();
+        let existing = self.find_slugs_for_url(&url).into_iter()
+            .filter_map(|slug| self.stats.get(&slug))
+            .find(|stats| self.redirect_gate(stats).check(now).is_ok())
+            .map(|stats| stats.link.clone());
+
+        let result = match existing {
+            Some(link) => Ok(link),
+            None => self.create_short_link(&url, None, None)
+        };
+        self.after_command();
+
+        result
+    }
+
+    fn handle_set_serving_hints(
+        &mut self,
+        slug: Slug,
+        hints: Option<ServingHints>,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.set_serving_hints(hints);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_mark_dead(
+        &mut self,
+        slug: Slug,
+        evidence: String,
+    ) -> Result<(), ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.mark_dead(evidence);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_mark_alive(
+        &mut self,
+        slug: Slug,
+    ) -> Result<(), ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.mark_alive();
+        self.after_command();
+
+        result
+    }
+
+    fn handle_set_dead_link_policy(
+        &mut self,
+        slug: Slug,
+        policy: Option<DeadLinkPolicy>,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.set_dead_link_policy(policy);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_make_permanent(
+        &mut self,
+        slug: Slug,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.make_permanent();
+        self.after_command();
+
+        result
+    }
+
+    fn handle_delete_short_link(
+        &mut self,
+        slug: Slug,
+    ) -> Result<(), ShortenerError> {
+        self.delete_short_link(slug, false)
+    }
+
+    fn handle_update_target(
+        &mut self,
+        slug: Slug,
+        new_url: Url,
+    ) -> Result<ShortLink, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.update_target(&new_url);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_set_link_enabled(
+        &mut self,
+        slug: Slug,
+        enabled: bool,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.set_enabled(enabled);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_issue_stats_token(
+        &mut self,
+        slug: Slug,
+        expires_at: SystemTime,
+        scope: StatsScope,
+    ) -> Result<StatsToken, ShortenerError> {
+        let token = StatsGrants::generate(self.log.len());
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.issue_stats_token(StatsGrants::hash(token.as_str()), expires_at, scope);
+        self.after_command();
+
+        result.map(|()| token)
+    }
+
+    fn handle_set_expiry(
+        &mut self,
+        slug: Slug,
+        expires_at: Option<SystemTime>,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.set_expiry(expires_at);
+        self.after_command();
+
+        result
+    }
+
+    fn handle_set_expiry_in(
+        &mut self,
+        slug: Slug,
+        ttl: Duration,
+    ) -> Result<commands::CommandOutcome, ShortenerError> {
+        let expires_at = self.clock.now().checked_add(ttl);
+        commands::ExtendedCommandHandler::handle_set_expiry(self, slug, expires_at)
+    }
+
+    fn handle_revoke_stats_token(&mut self, token: &str) -> Result<(), ShortenerError> {
+        let token_hash = StatsGrants::hash(token);
+        let slug = match self.stats_grants.grants.get(&token_hash) {
+            Some(grant) => grant.slug.clone(),
+            None => return Err(ShortenerError::InvalidStatsToken)
+        };
+
+        let mut aggregate = ShortLinkAggregate::new(self);
+        aggregate.rehydrate_by_slug(&slug);
+        let result = aggregate.revoke_stats_token(token_hash);
+        self.after_command();
+
+        result
+    }
+}
+
+impl queries::QueryHandler for UrlShortenerService {
+    fn get_stats(&self, slug: Slug) -> Result<Stats, ShortenerError> {
+        let stats_result = self.stats.get(&slug);
+        match stats_result {
+            Some(stats) => { Ok(stats.clone()) }
+            None => { Err(ShortenerError::SlugNotFound) }
+        }
+    }
+}
+
+impl queries::ExtendedQueryHandler for UrlShortenerService {
+    fn get_stats_batch(&self, slugs: &[Slug]) -> BulkOutcome<Stats> {
+        self.get_stats_bulk(slugs)
+    }
+
+    fn list_links(&self, cursor: Option<&str>, limit: usize) -> Result<LinkPage, CursorError> {
+        let after = cursor.map(|cursor| self.parse_cursor(cursor)).transpose()?;
+        let mut remaining = match after {
+            Some(sequence) => self.creation_order.links.range(sequence.saturating_add(1)..),
+            None => self.creation_order.links.range(..)
+        };
+
+        let page: Vec<(u64, &Slug)> = remaining.by_ref()
+            .take(limit)
+            .map(|(sequence, slug)| (*sequence, slug))
+            .collect();
+        let next = match page.last() {
+            Some((sequence, _)) if remaining.next().is_some() => Some(self.issue_cursor(*sequence)),
+            _ => None
+        };
+
+        Ok(LinkPage {
+            links: page.into_iter().map(|(_, slug)| self.stats[slug].clone()).collect(),
+            next
+        })
+    }
+
+    fn find_by_url(&self, url: Url) -> Vec<Slug> {
+        self.find_slugs_for_url(&url)
+    }
+
+    fn total_redirects(&self) -> u64 {
+        self.totals.redirects
+    }
+
+    /// Unlike [`UrlShortenerService::leaderboard`] this needs no
+    /// configuration, at the cost of a pass over every link per call.
+    fn top_links(&self, n: usize) -> Vec<Stats> {
+        let mut top: Vec<&Stats> = self.stats.values().collect();
+        top.sort_by(|a, b| b.redirects.cmp(&a.redirects).then_with(|| a.link.slug.cmp(&b.link.slug)));
+
+        top.into_iter().take(n).cloned().collect()
+    }
+
+    fn get_stats_with_token(&self, token: &str) -> Result<SharedStats, ShortenerError> {
+        let grant = self.stats_grants.grants.get(&StatsGrants::hash(token))
+            .filter(|grant| grant.expires_at > self.clock.now())
+            .ok_or(ShortenerError::InvalidStatsToken)?;
+        let stats = self.stats.get(&grant.slug).ok_or(ShortenerError::InvalidStatsToken)?;
+
+        Ok(SharedStats {
+            stats: stats.clone(),
+            outcomes: grant.scope.outcomes
+                .then(|| self.outcomes.get(&grant.slug).cloned().unwrap_or_default()),
+            destinations: match grant.scope.destinations {
+                true => Some(self.get_redirects_by_url(&grant.slug)?),
+                false => None
+            }
+        })
+    }
+}
+
+mod events {
+    use std::time::{Duration, SystemTime};
+    use super::{DeadLinkPolicy, InvalidUrlReason, LinkProtection, ServingHints, Slug, StatsScope, Url};
+
+    /// A recorded event of a short link.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct Event {
+        pub slug: Slug,
+        /// When the event was recorded. Events logged before timestamps
+        /// existed are read as [`SystemTime::UNIX_EPOCH`], see
+        /// [`UrlShortenerService::backfill_timestamps`](crate::UrlShortenerService::backfill_timestamps).
+        #[cfg_attr(feature = "serde", serde(default = "unrecorded"))]
+        pub timestamp: SystemTime,
+        pub event_type: EventType
+    }
+
+    /// The timestamp of an event logged before timestamps existed.
+    #[cfg(feature = "serde")]
+    fn unrecorded() -> SystemTime {
+        SystemTime::UNIX_EPOCH
+    }
+
+    /// Something that happened to a short link.
+    #[derive(Clone, Debug, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum EventType {
+        /// The link was created, optionally expiring at `expires_at`.
+        ShortLinkCreated {
+            url: Url,
+            expires_at: Option<SystemTime>
+        },
+        /// A redirect was served. `url` is the destination actually returned
+        /// to the client, recorded so later URL changes cannot re-attribute it.
+        ShortLinkRedirected {
+            url: Url,
+            lookup: LookupNormalization
+        },
+        /// Serving hints were set.
+        ServingHintsSet(ServingHints),
+        /// Serving hints were removed.
+        ServingHintsCleared,
+        /// Redirects counted by another system before the link was migrated
+        /// here, compacted into a single event.
+        RedirectsMigrated(u64),
+        /// The destination was found dead; carries the evidence.
+        LinkMarkedDead(String),
+        /// The destination was found alive again.
+        LinkMarkedAlive,
+        /// The link's own dead-link policy was set or, with `None`, removed.
+        DeadLinkPolicySet(Option<DeadLinkPolicy>),
+        /// The link was frozen.
+        MarkedPermanent,
+        /// The link's permanence was lifted to delete it anyway.
+        PermanenceOverridden,
+        /// The link's bootstrap seed set its protection. A reserved slug
+        /// stays reserved after the link is deleted.
+        ProtectionSet(LinkProtection),
+        /// The link's tags were replaced.
+        TagsSet(Vec<String>),
+        /// A token to read the link's stats was issued; only its hash is
+        /// recorded.
+        StatsTokenIssued {
+            token_hash: u64,
+            expires_at: SystemTime,
+            scope: StatsScope
+        },
+        /// A stats token of the link was revoked.
+        StatsTokenRevoked {
+            token_hash: u64
+        },
+        /// The link was deleted, freeing its slug.
+        ShortLinkDeleted,
+        /// The link was pointed to a new URL.
+        TargetUrlUpdated(Url),
+        /// The link was paused.
+        ShortLinkDisabled,
+        /// The link was resumed.
+        ShortLinkEnabled,
+        /// The link was made to work for a single redirect only.
+        MarkedOneTime,
+        /// The single redirect of a one-time link was served; the link
+        /// resolves no more.
+        OneTimeLinkConsumed,
+        /// The link was limited to the given number of redirects, counting
+        /// those already served.
+        RedirectLimitSet(u64),
+        /// The link's expiry was set or, with `None`, removed.
+        ExpirySet(Option<SystemTime>),
+        /// The timestamps of this many of the link's events, logged before
+        /// timestamps existed, were backfilled, see
+        /// [`UrlShortenerService::backfill_timestamps`](crate::UrlShortenerService::backfill_timestamps).
+        TimestampsBackfilled(u64),
+        /// The link's upcoming expiry was announced to the notifier with the
+        /// given lead time, see
+        /// [`UrlShortenerService::with_expiry_notifier`](crate::UrlShortenerService::with_expiry_notifier).
+        ExpiryNoticeSent(Duration),
+        /// The migrated link's URL fails validation for the given reason but
+        /// was kept, see [`MigrationOptions::lenient_urls`](super::MigrationOptions::lenient_urls).
+        UrlGrandfathered(InvalidUrlReason),
+        /// An event of a command defined outside this crate, recorded with
+        /// the `extension` feature. Only custom projections interpret it.
+        Custom {
+            name: String,
+            payload: String
+        }
+    }
+
+    /// Normalization that resolved the requested slug to the stored one.
+    #[derive(Clone, Copy, Debug, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub enum LookupNormalization {
+        /// The slug matched as requested.
+        Exact,
+        /// A trailing slash was ignored.
+        TrailingSlash,
+        /// The branded display form was mapped back to the slug.
+        DisplayForm,
+        /// Case or ambiguous characters were folded.
+        Folded
+    }
+}
+
+impl domain::EventBroker for UrlShortenerService {
+    fn slug_id(&self, slug: &Slug) -> Option<SlugId> {
+        self.index.get(slug)
+    }
+
+    fn intern(&mut self, slug: &Slug) -> SlugId {
+        self.index.intern(slug)
+    }
+
+    fn publish_event(&mut self, id: SlugId, event: &Event) {
+        // Save event to event store, indexed by slug id. This is the only
+        // place events are appended, which upholds the ordering guarantee.
+        self.index.push(id, self.log.len());
+        self.log.push(event.clone());
+
+        // Update Query Model
+        self.project_event(event);
+    }
+
+    fn iter_by_id_from(&self, id: SlugId, from: usize) -> Box<dyn Iterator<Item = &Event> + '_> {
+        let positions = self.index.positions(id).get(from..).unwrap_or(&[]);
+        Box::new(positions.iter().map(|&position| &self.log[position]))
+    }
+
+    fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    fn snapshot_interval(&self) -> Option<usize> {
+        self.snapshot_interval
+    }
+
+    fn load_snapshot(&self, id: SlugId) -> Option<domain::Snapshot> {
+        self.index.snapshot(id).cloned()
+    }
+
+    fn save_snapshot(&mut self, id: SlugId, snapshot: domain::Snapshot) {
+        self.index.set_snapshot(id, snapshot);
+    }
+}
+
+mod domain {
+    use std::time::{Duration, SystemTime};
+    use super::events::{Event, EventType};
+    use super::events::LookupNormalization;
+    use super::commands::CommandOutcome;
+    use super::{
+        DeadLinkPolicy, InvalidUrlReason, LinkProtection, LookupLeniency, ServiceUrlValidator, ServingHints, ShortLink,
+        ShortenerError, Slug, SlugId, StatsScope, TransitionError, Url, UrlValidator
+    };
+
+    /// Stores events and projects them into read models; implemented by
+    /// [`UrlShortenerService`](super::UrlShortenerService).
+    pub trait EventBroker {
+        /// Returns the id of a slug that has events, or `None` if it has
+        /// none.
+        fn slug_id(&self, slug: &Slug) -> Option<SlugId>;
+
+        /// Returns the id of a slug, handing out a new one if the slug has
+        /// no events yet.
+        fn intern(&mut self, slug: &Slug) -> SlugId;
+
+        /// Appends a new event of the link with id `id` to the store and
+        /// applies it to the read models.
+        fn publish_event(&mut self, id: SlugId, event: &Event);
+
+        /// The time new events are stamped with.
+        fn now(&self) -> SystemTime;
+
+        /// Returns the events of the link with id `id` from position `from`
+        /// on, in the order they were recorded, borrowed from the store.
+        fn iter_by_id_from(&self, id: SlugId, from: usize) -> Box<dyn Iterator<Item = &Event> + '_>;
+
+        /// Returns a slug's events in the order they were recorded, borrowed
+        /// from the store.
+        fn iter_by_slug(&self, slug: &Slug) -> Box<dyn Iterator<Item = &Event> + '_> {
+            self.iter_by_slug_from(slug, 0)
+        }
+
+        /// Returns a slug's events from position `from` on.
+        fn iter_by_slug_from(&self, slug: &Slug, from: usize) -> Box<dyn Iterator<Item = &Event> + '_> {
+            match self.slug_id(slug) {
+                Some(id) => self.iter_by_id_from(id, from),
+                None => Box::new(std::iter::empty())
+            }
+        }
+
+        /// Number of events after which an aggregate saves a [`Snapshot`],
+        /// `None` to never save one.
+        fn snapshot_interval(&self) -> Option<usize> {
+            None
+        }
+
+        /// Returns the latest snapshot of the aggregate of the link with id
+        /// `id`, if any.
+        fn load_snapshot(&self, id: SlugId) -> Option<Snapshot> {
+            None
+        }
+
+        /// Keeps a snapshot of the link with id `id`, replacing any older
+        /// one.
+        fn save_snapshot(&mut self, id: SlugId, snapshot: Snapshot) {}
+    }
+
+    /// Everything an aggregate knows about its link.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct LinkState {
+        link: ShortLink,
+        serving_hints: Option<ServingHints>,
+        dead: bool,
+        dead_link_policy: Option<DeadLinkPolicy>,
+        permanent: bool,
+        protection: LinkProtection,
+        tags: Vec<String>,
+        stats_tokens: Vec<u64>,
+        expires_at: Option<SystemTime>,
+        disabled: bool,
+        one_time: bool,
+        consumed: bool,
+        redirects: u64,
+        redirect_limit: Option<u64>,
+        deleted: bool
+    }
+
+    impl LinkState {
+        /// State of a slug with no link, before creation or after deletion.
+        fn empty(slug: Slug) -> Self {
+            Self {
+                link: ShortLink {
+                    slug,
+                    url: Url("".to_string())
+                },
+                serving_hints: None,
+                dead: false,
+                dead_link_policy: None,
+                permanent: false,
+                protection: LinkProtection::default(),
+                tags: Vec::new(),
+                stats_tokens: Vec::new(),
+                expires_at: None,
+                disabled: false,
+                one_time: false,
+                consumed: false,
+                redirects: 0,
+                redirect_limit: None,
+                deleted: false
+            }
+        }
+
+        /// State of a slug with no events.
+        pub fn new(slug: Slug) -> Self {
+            Self::empty(slug)
+        }
+
+        /// Returns the slug the state belongs to.
+        pub fn slug(&self) -> &Slug {
+            &self.link.slug
+        }
+
+        /// Checks that `event` can follow the events applied so far: only a
+        /// creation may come while there is no link, and only while there
+        /// is none. Backfill audits may come at any time.
+        pub fn check(&self, event: &Event) -> Result<(), TransitionError> {
+            let exists = !self.link.url.0.is_empty();
+            match (&event.event_type, exists) {
+                (EventType::TimestampsBackfilled(_), _) => Ok(()),
+                (EventType::ShortLinkCreated { .. }, true) => Err(TransitionError::AlreadyCreated),
+                (EventType::ShortLinkCreated { .. }, false) => Ok(()),
+                (_, false) if self.deleted => Err(TransitionError::Deleted),
+                (_, false) => Err(TransitionError::NotCreated),
+                (_, true) => Ok(())
+            }
+        }
+
+        /// Applies an event to the state only, so rehydrating never touches
+        /// the event store or read models.
+        pub fn apply(&mut self, event: &Event) {
+            match &event.event_type {
+                EventType::ShortLinkCreated { url, expires_at, .. } => {
+                    self.link.slug = event.slug.clone();
+                    self.link.url = url.clone();
+                    self.expires_at = *expires_at;
+                    self.deleted = false;
+                }
+                EventType::ServingHintsSet(hints) => {
+                    self.serving_hints = Some(hints.clone());
+                }
+                EventType::ServingHintsCleared => {
+                    self.serving_hints = None;
+                }
+                EventType::LinkMarkedDead(_) => {
+                    self.dead = true;
+                }
+                EventType::LinkMarkedAlive => {
+                    self.dead = false;
+                }
+                EventType::DeadLinkPolicySet(policy) => {
+                    self.dead_link_policy = *policy;
+                }
+                EventType::MarkedPermanent => {
+                    self.permanent = true;
+                }
+                EventType::PermanenceOverridden => {
+                    self.permanent = false;
+                }
+                EventType::ProtectionSet(protection) => {
+                    self.protection = *protection;
+                }
+                EventType::TagsSet(tags) => {
+                    self.tags = tags.clone();
+                }
+                EventType::StatsTokenIssued { token_hash, .. } => {
+                    self.stats_tokens.push(*token_hash);
+                }
+                EventType::StatsTokenRevoked { token_hash } => {
+                    self.stats_tokens.retain(|issued| issued != token_hash);
+                }
+                EventType::TargetUrlUpdated(url) => {
+                    self.link.url = url.clone();
+                    self.dead = false;
+                }
+                EventType::ShortLinkDisabled => {
+                    self.disabled = true;
+                }
+                EventType::ShortLinkEnabled => {
+                    self.disabled = false;
+                }
+                EventType::MarkedOneTime => {
+                    self.one_time = true;
+                }
+                EventType::OneTimeLinkConsumed => {
+                    self.consumed = true;
+                }
+                EventType::RedirectLimitSet(limit) => {
+                    self.redirect_limit = Some(*limit);
+                }
+                EventType::ExpirySet(expires_at) => {
+                    self.expires_at = *expires_at;
+                }
+                EventType::ShortLinkRedirected { .. } => {
+                    self.redirects = self.redirects.saturating_add(1);
+                }
+                EventType::RedirectsMigrated(redirects) => {
+                    self.redirects = self.redirects.saturating_add(*redirects);
+                }
+                EventType::ShortLinkDeleted => {
+                    let reserved = self.protection.reserved;
+                    *self = LinkState::empty(event.slug.clone());
+                    self.protection.reserved = reserved;
+                    self.deleted = true;
+                }
+                EventType::UrlGrandfathered(_)
+                | EventType::ExpiryNoticeSent(_)
+                | EventType::TimestampsBackfilled(_)
+                | EventType::Custom { .. } => {}
+            }
+        }
+
+        /// The redirect gates of the link; `default_dead_link_policy` applies
+        /// if the link has no policy of its own.
+        fn redirect_gate(&self, default_dead_link_policy: DeadLinkPolicy) -> RedirectGate {
+            RedirectGate {
+                consumed: self.consumed,
+                disabled: self.disabled,
+                expires_at: self.expires_at,
+                redirects: self.redirects,
+                redirect_limit: self.redirect_limit,
+                dead: self.dead,
+                dead_link_policy: self.dead_link_policy.unwrap_or(default_dead_link_policy)
+            }
+        }
+    }
+
+    /// What decides whether an existing link may serve a redirect, gathered
+    /// from an aggregate's state or from the read models, so commands and
+    /// read-only lookups refuse the same links.
+    pub struct RedirectGate {
+        pub consumed: bool,
+        pub disabled: bool,
+        pub expires_at: Option<SystemTime>,
+        pub redirects: u64,
+        pub redirect_limit: Option<u64>,
+        pub dead: bool,
+        pub dead_link_policy: DeadLinkPolicy
+    }
+
+    impl RedirectGate {
+        /// Checks the gates at `now` in the order documented on
+        /// [`decide_redirect`](super::UrlShortenerService::decide_redirect),
+        /// failing with the error of the first closed one.
+        pub fn check(&self, now: SystemTime) -> Result<(), ShortenerError> {
+            if self.consumed {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            if self.disabled {
+                return Err(ShortenerError::LinkDisabled);
+            }
+
+            if self.expires_at.is_some_and(|expires_at| expires_at <= now) {
+                return Err(ShortenerError::LinkExpired);
+            }
+
+            if self.redirect_limit.is_some_and(|limit| self.redirects >= limit) {
+                return Err(ShortenerError::LinkExhausted);
+            }
+
+            if self.dead && self.dead_link_policy == DeadLinkPolicy::ReturnError {
+                return Err(ShortenerError::LinkDead);
+            }
+
+            Ok(())
+        }
+    }
+
+    /// An aggregate's state after its first `version` events, so rehydrating
+    /// only replays the events recorded since.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Snapshot {
+        /// Number of the link's events the state reflects.
+        pub version: usize,
+
+        /// The aggregate's state after those events.
+        pub state: LinkState
+    }
+
+    /// The consistency boundary of a short link. Commands run on an aggregate
+    /// restored with [`ShortLinkAggregate::rehydrate_by_slug`]; each checks
+    /// the link's state and records its events through the broker.
+    pub struct ShortLinkAggregate<'a> {
+        broker: &'a mut dyn EventBroker,
+        id: Option<SlugId>,
+        state: LinkState,
+        version: usize
+    }
+
+    impl<'a> ShortLinkAggregate<'a> {
+        pub fn new(eb: &'a mut dyn EventBroker) -> Self {
+            Self {
+                broker: eb,
+                id: None,
+                state: LinkState::empty(Slug("".to_string())),
+                version: 0
+            }
+        }
+
+        /// Returns the link, or `None` if the slug has none.
+        pub fn link(&self) -> Option<&ShortLink> {
+            (!self.state.link.url.0.is_empty()).then_some(&self.state.link)
+        }
+
+        /// Returns everything the aggregate knows about its link.
+        pub fn state(&self) -> &LinkState {
+            &self.state
+        }
+
+        /// Restores the link's state from its latest snapshot, if any, and
+        /// the events recorded after it. Returns how many events were
+        /// replayed.
+        pub fn rehydrate_by_slug(&mut self, slug: &Slug) -> usize {
+            // The only lookup by slug; everything below goes by id.
+            self.id = self.broker.slug_id(slug);
+            let Some(id) = self.id else {
+                self.state.link.slug = slug.clone();
+                return 0;
+            };
+
+            match self.broker.load_snapshot(id) {
+                Some(snapshot) => {
+                    self.state = snapshot.state;
+                    self.version = snapshot.version;
+                }
+                None => self.state.link.slug = slug.clone()
+            }
+
+            let mut replayed = 0;
+            for event in self.broker.iter_by_id_from(id, self.version) {
+                self.state.apply(event);
+                replayed += 1;
+            }
+            self.version += replayed;
+
+            if self.broker.snapshot_interval().is_some_and(|interval| replayed >= interval) {
+                self.save_snapshot(id);
+            }
+
+            replayed
+        }
+
+        /// Publishes a new event and applies it to the aggregate's state.
+        /// The link's first event interns its slug.
+        fn raise(&mut self, event: &Event) {
+            let id = match self.id {
+                Some(id) => id,
+                None => *self.id.insert(self.broker.intern(&event.slug))
+            };
+            self.broker.publish_event(id, event);
+            self.state.apply(event);
+            self.version += 1;
+
+            if self.broker.snapshot_interval().is_some_and(|interval| self.version.is_multiple_of(interval)) {
+                self.save_snapshot(id);
+            }
+        }
+
+        fn save_snapshot(&mut self, id: SlugId) {
+            self.broker.save_snapshot(id, Snapshot {
+                version: self.version,
+                state: self.state.clone()
+            });
+        }
+
+        pub fn create_short_link(
+            &mut self,
+            url: &Url,
+            expires_at: Option<SystemTime>
+        ) -> Result<ShortLink, ShortenerError> {
+            if self.state.link.url.0.is_empty() && self.state.protection.reserved {
+                return Err(ShortenerError::ProtectedLink);
+            }
+
+            self.create_seeded(url, expires_at)
+        }
+
+        /// Creates the link like [`ShortLinkAggregate::create_short_link`],
+        /// even if its slug is reserved for a seed.
+        pub fn create_seeded(
+            &mut self,
+            url: &Url,
+            expires_at: Option<SystemTime>
+        ) -> Result<ShortLink, ShortenerError> {
+            if !self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugAlreadyInUse);
+            }
+
+            validate_slug(&self.state.link.slug)?;
+
+            if !is_valid_url(url) {
+                return Err(ShortenerError::InvalidUrl);
+            }
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::ShortLinkCreated {
+                    url: ServiceUrlValidator::default().normalize(url),
+                    expires_at
+                }
+            };
+
+            self.raise(&event);
+
+            Ok(self.state.link.clone())
+        }
+
+        /// Records a link taken over from another system at `created_at`,
+        /// with the redirects it counted there. The URL is stored normalized
+        /// and expected to be validated by the caller, which passes the
+        /// reason it fails validation if the link is grandfathered anyway.
+        pub fn migrate(
+            &mut self,
+            url: &Url,
+            redirects: u64,
+            created_at: SystemTime,
+            grandfathered: Option<InvalidUrlReason>
+        ) -> Result<ShortLink, ShortenerError> {
+            if !self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugAlreadyInUse);
+            }
+
+            if self.state.protection.reserved {
+                return Err(ShortenerError::ProtectedLink);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: created_at,
+                event_type: EventType::ShortLinkCreated {
+                    url: ServiceUrlValidator::default().normalize(url),
+                    expires_at: None
+                }
+            });
+
+            if let Some(reason) = grandfathered {
+                self.raise(&Event {
+                    slug: self.state.link.slug.clone(),
+                    timestamp: created_at,
+                    event_type: EventType::UrlGrandfathered(reason)
+                });
+            }
+
+            if redirects > 0 {
+                self.raise(&Event {
+                    slug: self.state.link.slug.clone(),
+                    timestamp: created_at,
+                    event_type: EventType::RedirectsMigrated(redirects)
+                });
+            }
+
+            Ok(self.state.link.clone())
+        }
+
+        /// Records an event taken from another log, checking only that it
+        /// fits the link's history: a creation needs a free slug, anything
+        /// else an existing link.
+        pub fn replay(&mut self, event: &Event) -> Result<(), TransitionError> {
+            self.state.check(event)?;
+            self.raise(event);
+
+            Ok(())
+        }
+
+        /// Records an event of a command defined outside this crate. Custom
+        /// events don't change the aggregate's state, so they are allowed on
+        /// permanent links too.
+        pub fn emit_custom(&mut self, name: String, payload: String) -> Result<(), ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::Custom { name, payload }
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        /// Fails unless the link exists and may still be changed.
+        fn ensure_mutable(&self) -> Result<(), ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            if self.state.permanent {
+                return Err(ShortenerError::LinkPermanent);
+            }
+
+            Ok(())
+        }
+
+        pub fn update_target(&mut self, url: &Url) -> Result<ShortLink, ShortenerError> {
+            self.ensure_mutable()?;
+
+            if !is_valid_url(url) {
+                return Err(ShortenerError::InvalidUrl);
+            }
+
+            let url = ServiceUrlValidator::default().normalize(url);
+            if url == self.state.link.url {
+                return Ok(self.state.link.clone());
+            }
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::TargetUrlUpdated(url)
+            };
+
+            self.raise(&event);
+
+            Ok(self.state.link.clone())
+        }
+
+        pub fn set_enabled(&mut self, enabled: bool) -> Result<CommandOutcome, ShortenerError> {
+            self.ensure_mutable()?;
+
+            if enabled != self.state.disabled {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            let event_type = if enabled {
+                EventType::ShortLinkEnabled
+            } else {
+                EventType::ShortLinkDisabled
+            };
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type
+            };
+
+            self.raise(&event);
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        /// Deletes the link. A permanent link is only deleted with
+        /// `override_permanent`, which records the override first.
+        pub fn delete(&mut self, override_permanent: bool) -> Result<(), ShortenerError> {
+            if self.state.protection.undeletable && !self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::ProtectedLink);
+            }
+
+            if override_permanent && self.state.permanent {
+                self.raise(&Event {
+                    slug: self.state.link.slug.clone(),
+                    timestamp: self.broker.now(),
+                    event_type: EventType::PermanenceOverridden
+                });
+            }
+
+            self.ensure_mutable()?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::ShortLinkDeleted
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        /// Records that `events` of the link's events had their timestamps
+        /// backfilled. Allowed whether or not the link exists, as it only
+        /// audits its history.
+        pub fn record_backfill(&mut self, events: u64) {
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::TimestampsBackfilled(events)
+            });
+        }
+
+        /// Records that the link's upcoming expiry was announced with `lead`.
+        /// Allowed on permanent links too, as it doesn't change the link.
+        pub fn record_expiry_notice(&mut self, lead: Duration) -> Result<(), ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::ExpiryNoticeSent(lead)
+            });
+
+            Ok(())
+        }
+
+        /// Sets when an existing link expires, `None` for never.
+        pub fn set_expiry(&mut self, expires_at: Option<SystemTime>) -> Result<CommandOutcome, ShortenerError> {
+            self.ensure_mutable()?;
+
+            if expires_at == self.state.expires_at {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::ExpirySet(expires_at)
+            });
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        /// Limits an existing link to `limit` redirects in total.
+        pub fn set_redirect_limit(&mut self, limit: u64) -> Result<(), ShortenerError> {
+            self.ensure_mutable()?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::RedirectLimitSet(limit)
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        /// Limits an existing link to a single redirect.
+        pub fn make_one_time(&mut self) -> Result<(), ShortenerError> {
+            self.ensure_mutable()?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::MarkedOneTime
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        /// Sets the protection of an existing link. Allowed on permanent
+        /// links, as it doesn't change where the link points.
+        pub fn set_protection(&mut self, protection: LinkProtection) -> Result<CommandOutcome, ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            if protection == self.state.protection {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::ProtectionSet(protection)
+            });
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        /// Replaces the tags of an existing link, including a permanent
+        /// one.
+        pub fn set_tags(&mut self, tags: &[String]) -> Result<CommandOutcome, ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            if tags == self.state.tags.as_slice() {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::TagsSet(tags.to_vec())
+            });
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        /// Records a stats token of an existing link by its hash, including
+        /// on a permanent link, as sharing its stats doesn't change it.
+        pub fn issue_stats_token(
+            &mut self,
+            token_hash: u64,
+            expires_at: SystemTime,
+            scope: StatsScope
+        ) -> Result<(), ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::StatsTokenIssued { token_hash, expires_at, scope }
+            });
+
+            Ok(())
+        }
+
+        /// Revokes an outstanding stats token of the link by its hash.
+        pub fn revoke_stats_token(&mut self, token_hash: u64) -> Result<(), ShortenerError> {
+            if !self.state.stats_tokens.contains(&token_hash) {
+                return Err(ShortenerError::InvalidStatsToken);
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::StatsTokenRevoked { token_hash }
+            });
+
+            Ok(())
+        }
+
+        pub fn make_permanent(&mut self) -> Result<CommandOutcome, ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound);
+            }
+
+            if self.state.permanent {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::MarkedPermanent
+            };
+
+            self.raise(&event);
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        pub fn set_serving_hints(&mut self, hints: Option<ServingHints>) -> Result<CommandOutcome, ShortenerError> {
+            self.ensure_mutable()?;
+
+            if let Some(hints) = &hints {
+                validate_serving_hints(hints)?;
+            }
+
+            if hints == self.state.serving_hints {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            let event_type = match hints {
+                Some(hints) => EventType::ServingHintsSet(hints),
+                None => EventType::ServingHintsCleared
+            };
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type
+            };
+
+            self.raise(&event);
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        pub fn mark_dead(&mut self, evidence: String) -> Result<(), ShortenerError> {
+            self.ensure_mutable()?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::LinkMarkedDead(evidence)
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        pub fn mark_alive(&mut self) -> Result<(), ShortenerError> {
+            self.ensure_mutable()?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::LinkMarkedAlive
+            };
+
+            self.raise(&event);
+
+            Ok(())
+        }
+
+        pub fn set_dead_link_policy(&mut self, policy: Option<DeadLinkPolicy>) -> Result<CommandOutcome, ShortenerError> {
+            self.ensure_mutable()?;
+
+            if policy == self.state.dead_link_policy {
+                return Ok(CommandOutcome::Unchanged);
+            }
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: self.broker.now(),
+                event_type: EventType::DeadLinkPolicySet(policy)
+            };
+
+            self.raise(&event);
+
+            Ok(CommandOutcome::Applied)
+        }
+
+        /// Records a redirect happening at `now`. `default_dead_link_policy`
+        /// applies if the link is marked dead and has no policy of its own.
+        pub fn redirect(
+            &mut self,
+            lookup: LookupNormalization,
+            default_dead_link_policy: DeadLinkPolicy,
+            now: SystemTime
+        ) -> Result<ShortLink, ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound)
+            }
+
+            self.state.redirect_gate(default_dead_link_policy).check(now)?;
+
+            let event = Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: now,
+                event_type: EventType::ShortLinkRedirected {
+                    url: self.state.link.url.clone(),
+                    lookup
+                }
+            };
+
+            self.raise(&event);
+
+            if self.state.one_time {
+                self.raise(&Event {
+                    slug: self.state.link.slug.clone(),
+                    timestamp: now,
+                    event_type: EventType::OneTimeLinkConsumed
+                });
+            }
+
+            Ok(self.state.link.clone())
+        }
+
+        /// Records, at `now`, a redirect to `url` that was already served
+        /// after the gates were checked. The link only has to still exist:
+        /// a gate closing in between doesn't lose the click, and a one-time
+        /// link is consumed unless an earlier click already did.
+        pub fn record_served(
+            &mut self,
+            url: Url,
+            lookup: LookupNormalization,
+            now: SystemTime
+        ) -> Result<ShortLink, ShortenerError> {
+            if self.state.link.url.0.is_empty() {
+                return Err(ShortenerError::SlugNotFound)
+            }
+
+            self.raise(&Event {
+                slug: self.state.link.slug.clone(),
+                timestamp: now,
+                event_type: EventType::ShortLinkRedirected { url, lookup }
+            });
+
+            if self.state.one_time && !self.state.consumed {
+                self.raise(&Event {
+                    slug: self.state.link.slug.clone(),
+                    timestamp: now,
+                    event_type: EventType::OneTimeLinkConsumed
+                });
+            }
+
+            Ok(self.state.link.clone())
+        }
+    }
+
+    pub const MAX_SLUG_LEN: usize = 64;
+
+    /// Checks that a slug is usable as a single path segment.
+    pub fn validate_slug(slug: &Slug) -> Result<(), ShortenerError> {
+        let valid = !slug.0.is_empty() && slug.0.len() <= MAX_SLUG_LEN &&
+            slug.0.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+        if valid {
+            Ok(())
+        } else {
+            Err(ShortenerError::InvalidSlug)
+        }
+    }
+
+    const MAX_EXTRA_HEADERS: usize = 16;
+    const MAX_HEADER_NAME_LEN: usize = 64;
+    const MAX_HEADER_VALUE_LEN: usize = 1024;
+
+    /// Checks that hints can be sent as HTTP headers: names must be RFC 7230
+    /// tokens other than `Location`, values must not contain control
+    /// characters, and everything must stay within the size caps.
+    fn validate_serving_hints(hints: &ServingHints) -> Result<(), ShortenerError> {
+        let is_valid_name = |name: &str| {
+            !name.is_empty() && name.len() <= MAX_HEADER_NAME_LEN &&
+                !name.eq_ignore_ascii_case("location") &&
+                name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c))
+        };
+        let is_valid_value = |value: &str| {
+            value.len() <= MAX_HEADER_VALUE_LEN &&
+                value.chars().all(|c| c == '\t' || !c.is_control())
+        };
+
+        let valid = hints.extra_headers.len() <= MAX_EXTRA_HEADERS &&
+            hints.cache_control.as_deref().is_none_or(is_valid_value) &&
+            hints.extra_headers.iter().all(|(name, value)| is_valid_name(name) && is_valid_value(value));
+
+        if valid { Ok(()) } else { Err(ShortenerError::InvalidServingHints) }
+    }
+
+    /// Collects every reason [`ShortLinkAggregate::create_short_link`] would
+    /// reject the given input, reading the event store without modifying it.
+    pub fn validate_create(
+        broker: &dyn EventBroker,
+        url: &Url,
+        slug: Option<&Slug>
+    ) -> Vec<ShortenerError> {
+        let mut issues = Vec::new();
+
+        if let Some(slug) = slug {
+            let in_use = broker.iter_by_slug(slug)
+                .filter(|event| {
+                    matches!(event.event_type, EventType::ShortLinkCreated { .. } | EventType::ShortLinkDeleted)
+                })
+                .last()
+                .is_some_and(|event| matches!(event.event_type, EventType::ShortLinkCreated { .. }));
+            if in_use {
+                issues.push(ShortenerError::SlugAlreadyInUse);
+            }
+            if let Err(error) = validate_slug(slug) {
+                issues.push(error);
+            }
+        }
+
+        if !is_valid_url(url) {
+            issues.push(ShortenerError::InvalidUrl);
+        }
+
+        issues
+    }
+
+    /// Returns the key under which a slug is indexed for folded lookups, or
+    /// `None` if the leniency enables no folding.
+    pub fn fold_slug(slug: &str, leniency: &LookupLeniency) -> Option<String> {
+        if !leniency.case_insensitive && !leniency.map_ambiguous_chars {
+            return None;
+        }
+
+        let ambiguous = leniency.map_ambiguous_chars;
+        let case_insensitive = leniency.case_insensitive;
+        let folded = slug.chars()
+            .map(|c| match c {
+                '0' | 'O' | 'o' if ambiguous => 'o',
+                '1' | 'I' | 'l' if ambiguous => 'l',
+                // `i` equals `I` when case is ignored, which is ambiguous with `l`
+                'i' if ambiguous && case_insensitive => 'l',
+                c if case_insensitive => c.to_ascii_lowercase(),
+                c => c
+            })
+            .collect();
+
+        Some(folded)
+    }
+
+    fn is_valid_url(url: &Url) -> bool {
+        ServiceUrlValidator::default().validate(url).is_ok()
+    }
+}
+
+impl From<&str> for Slug {
+    fn from(value: &str) -> Self {
+        Slug(value.to_string())
+    }
+}
+
+impl From<String> for Slug {
+    fn from(value: String) -> Self {
+        Slug(value)
+    }
+}
+
+impl AsRef<str> for Slug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Prints the raw slug; see [`UrlShortenerService::display_slug`] for the
+/// form configured to be shown to users.
+impl std::fmt::Display for Slug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Lets maps keyed by [`Slug`] be queried with a `&str`. Consistent with
+/// the derived `Eq`, `Hash` and `Ord`, which only look at the string.
+impl std::borrow::Borrow<str> for Slug {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Url {
+    fn from(value: &str) -> Self {
+        Url(value.to_string())
+    }
+}
+
+impl From<String> for Url {
+    fn from(value: String) -> Self {
+        Url(value)
+    }
+}
+
+impl AsRef<str> for Url {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for Url {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Prints the link as `slug -> url`.
+impl std::fmt::Display for ShortLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.slug, self.url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::commands::{CommandHandler, ExtendedCommandHandler};
+    use super::queries::QueryHandler;
+    use super::*;
+
+    fn clock() -> FixedClock {
+        FixedClock(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000))
+    }
+
+    /// Runs the same commands on a new service, so that two calls yield
+    /// services with equal read models.
+    fn busy_service() -> UrlShortenerService {
+        let mut service = UrlShortenerService::new().with_clock(clock());
+        for (slug, url) in [("a", "https://a.com"), ("b", "https://b.com"), ("c", "https://a.com")] {
+            service.handle_create_short_link(Url::from(url), Some(Slug::from(slug))).unwrap();
+        }
+        service.handle_redirect(Slug::from("a")).unwrap();
+        service.handle_redirect(Slug::from("b")).unwrap();
+        service.handle_delete_short_link(Slug::from("c")).unwrap();
+        service.handle_create_short_link(Url::from("https://c.com"), Some(Slug::from("c"))).unwrap();
+        service.handle_update_target(Slug::from("b"), Url::from("https://a.com")).unwrap();
+
+        service
+    }
+
+    #[test]
+    fn rebuild_matches_a_freshly_built_service() {
+        let fresh = busy_service();
+        let mut service = busy_service();
+        service.stats.get_mut(&Slug::from("a")).unwrap().redirects = 99;
+        service.stats.remove(&Slug::from("b"));
+        service.slugs_by_url.clear();
+        service.totals.redirects = 0;
+
+        service.rebuild_read_model();
+
+        assert_eq!(service.stats, fresh.stats);
+        assert_eq!(service.slugs_by_url, fresh.slugs_by_url);
+        assert_eq!(service.totals, fresh.totals);
+        assert_eq!(service.find_slugs_for_url(&Url::from("https://a.com")), [Slug::from("a"), Slug::from("b")]);
+    }
+
+    #[test]
+    fn rebuild_corrects_tampered_stats() {
+        let mut service = busy_service();
+        let expected = ["a", "b", "c"].map(|slug| service.get_stats(Slug::from(slug)));
+
+        let a = service.stats.get_mut(&Slug::from("a")).unwrap();
+        a.redirects = 42;
+        a.enabled = false;
+        a.link.url = Url::from("https://wrong.com");
+        service.stats.get_mut(&Slug::from("c")).unwrap().created_at = SystemTime::UNIX_EPOCH;
+        assert_ne!(service.get_stats(Slug::from("a")), expected[0]);
+
+        service.rebuild_read_model();
+
+        assert_eq!(["a", "b", "c"].map(|slug| service.get_stats(Slug::from(slug))), expected);
+        assert_eq!(service.assert_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn projected_counters_saturate() {
+        let mut service = busy_service();
+        service.stats.get_mut(&Slug::from("a")).unwrap().redirects = u64::MAX;
+        service.totals.redirects = u64::MAX;
+
+        service.handle_redirect(Slug::from("a")).unwrap();
+
+        assert_eq!(service.get_stats(Slug::from("a")).unwrap().redirects, u64::MAX);
+        assert_eq!(service.totals.redirects, u64::MAX);
+    }
+
+    #[test]
+    fn repair_fixes_every_corruption_class() {
+        let leniency = LookupLeniency { case_insensitive: true, ..LookupLeniency::default() };
+        let build = || {
+            let mut service = busy_service().with_lookup_leniency(leniency);
+            service.handle_set_serving_hints(Slug::from("a"), Some(ServingHints::default())).unwrap();
+            service
+        };
+        let mut service = build();
+        let ghost = Stats {
+            link: ShortLink { slug: Slug::from("ghost"), url: Url::from("https://g.com") },
+            ..service.stats[&Slug::from("a")].clone()
+        };
+        service.stats.insert(Slug::from("ghost"), ghost);
+        service.stats.get_mut(&Slug::from("a")).unwrap().redirects = 7;
+        service.stats.remove(&Slug::from("c"));
+        service.folded_slugs.insert("zombie".to_string(), Slug::from("zombie"));
+        service.folded_slugs.remove("b");
+        service.serving_hints.clear();
+
+        let report = service.repair(RepairMode::Report);
+
+        assert_eq!(report.orphan_stats, [Slug::from("ghost")]);
+        assert_eq!(report.mismatched_stats, [Slug::from("a")]);
+        assert_eq!(report.missing_stats, [Slug::from("c")]);
+        assert_eq!(report.dangling_index_entries, ["zombie"]);
+        assert_eq!(report.missing_index_entries, ["b"]);
+        assert_eq!(report.mismatched_serving_hints, [Slug::from("a")]);
+        assert_eq!(service.repair(RepairMode::Report), report);
+        assert!(service.stats.contains_key(&Slug::from("ghost")));
+
+        assert_eq!(service.repair(RepairMode::Fix), report);
+        assert!(service.repair(RepairMode::Fix).is_consistent());
+        assert_eq!(service.assert_invariants(), Ok(()));
+        assert_eq!(service.stats, build().stats);
+        assert_eq!(service.folded_slugs, build().folded_slugs);
+    }
+
+    #[test]
+    fn rehydration_publishes_nothing() {
+        let mut service = UrlShortenerService::new();
+        service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a"))).unwrap();
+        service.handle_redirect(Slug::from("a")).unwrap();
+        service.handle_create_short_link(Url::from("https://b.com"), Some(Slug::from("b"))).unwrap();
+        let slug = Slug::from("a");
+
+        let mut aggregate = ShortLinkAggregate::new(&mut service);
+        assert_eq!(aggregate.rehydrate_by_slug(&slug), 2);
+
+        assert_eq!(domain::EventBroker::iter_by_slug(&service, &slug).count(), 2);
+        assert_eq!(service.log.len(), 3);
+        assert_eq!(service.get_stats(slug).unwrap().redirects, 1);
+    }
+
+    #[test]
+    fn brokers_lend_events_from_the_log() {
+        let service = busy_service();
+        let broker: &dyn domain::EventBroker = &service;
+
+        for slug in ["a", "b", "c"].map(Slug::from) {
+            let borrowed: Vec<&Event> = broker.iter_by_slug(&slug).collect();
+            let positions = service.index.positions(service.index.get(&slug).unwrap());
+            assert_eq!(borrowed.len(), positions.len());
+            assert!(borrowed.iter().zip(positions).all(|(event, &position)| std::ptr::eq(*event, &service.log[position])));
+            assert_eq!(broker.iter_by_slug_from(&slug, 1).count(), positions.len() - 1);
+        }
+        assert_eq!(broker.iter_by_slug(&Slug::from("missing")).count(), 0);
+        assert_eq!(broker.iter_by_slug_from(&Slug::from("a"), 99).count(), 0);
+    }
+
+    #[test]
+    fn snapshots_bound_rehydration() {
+        let slug = Slug::from("hot");
+        let replayed = |interval| {
+            let mut service = UrlShortenerService::new().with_snapshot_interval(interval);
+            service.handle_create_short_link(Url::from("https://a.com"), Some(slug.clone())).unwrap();
+            for _ in 0..1_000 {
+                service.handle_redirect(slug.clone()).unwrap();
+            }
+
+            let mut aggregate = ShortLinkAggregate::new(&mut service);
+            let replayed = aggregate.rehydrate_by_slug(&slug);
+            assert_eq!(aggregate.link().map(|link| &link.url), Some(&Url::from("https://a.com")));
+            assert_eq!(service.get_stats(slug.clone()).unwrap().redirects, 1_000);
+
+            replayed
+        };
+
+        assert_eq!(replayed(0), 1_001);
+        assert!(replayed(100) < 100, "{}", replayed(100));
+    }
+
+    #[test]
+    fn snapshot_replay_matches_full_replay() {
+        let slug = Slug::from("hot");
+        let mut service = UrlShortenerService::new().with_snapshot_interval(1_000);
+        service.handle_create_short_link(Url::from("https://a.com"), Some(slug.clone())).unwrap();
+        service.handle_set_serving_hints(slug.clone(), Some(ServingHints::default())).unwrap();
+        for _ in 0..2_500 {
+            service.handle_redirect(slug.clone()).unwrap();
+        }
+        service.handle_mark_dead(slug.clone(), "503".to_string()).unwrap();
+        let id = service.index.get(&slug).unwrap();
+        assert_eq!(service.index.snapshot(id).map(|snapshot| snapshot.version), Some(2_000));
+
+        let mut from_snapshot = ShortLinkAggregate::new(&mut service);
+        assert_eq!(from_snapshot.rehydrate_by_slug(&slug), 503);
+        let from_snapshot = from_snapshot.state().clone();
+
+        service.index.clear_snapshots();
+        let mut full = ShortLinkAggregate::new(&mut service);
+        assert_eq!(full.rehydrate_by_slug(&slug), 2_503);
+        assert_eq!(full.state(), &from_snapshot);
+        assert_eq!(service.get_stats(slug).unwrap().redirects, 2_500);
+    }
+
+    #[test]
+    fn invariants_catch_corrupted_counters() {
+        let mut service = busy_service();
+        assert_eq!(service.assert_invariants(), Ok(()));
+
+        service.stats.get_mut(&Slug::from("a")).unwrap().redirects = 2;
+        let ghost = Stats {
+            link: ShortLink { slug: Slug::from("ghost"), url: Url::from("https://a.com") },
+            ..service.stats[&Slug::from("a")].clone()
+        };
+        service.stats.insert(Slug::from("ghost"), ghost);
+        service.stats.remove(&Slug::from("b"));
+
+        assert_eq!(
+            service.assert_invariants(),
+            Err(vec![
+                InvariantViolation::RedirectCountMismatch { slug: Slug::from("a"), events: 1, counter: 2 },
+                InvariantViolation::MissingStats { slug: Slug::from("b") },
+                InvariantViolation::OrphanStats { slug: Slug::from("ghost") },
+            ])
+        );
+    }
+
+    #[test]
+    fn invariants_catch_stale_urls() {
+        let mut service = busy_service();
+        service.stats.get_mut(&Slug::from("b")).unwrap().link.url = Url::from("https://b.com");
+
+        assert_eq!(
+            service.assert_invariants(),
+            Err(vec![InvariantViolation::UrlMismatch {
+                slug: Slug::from("b"),
+                events: Url::from("https://a.com"),
+                stored: Url::from("https://b.com"),
+            }])
+        );
+
+        service.rebuild_read_model();
+        assert_eq!(service.assert_invariants(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "invariants violated after command 2")]
+    fn periodic_checks_panic_on_violations() {
+        let mut service = UrlShortenerService::new().with_invariant_checks(2);
+        service.handle_create_short_link(Url::from("https://a.com"), Some(Slug::from("a"))).unwrap();
+        service.stats.get_mut(&Slug::from("a")).unwrap().redirects = 5;
+
+        let _ = service.handle_redirect(Slug::from("a"));
+    }
+
+    #[test]
+    fn filtered_slugs_never_reach_the_store() {
+        /// Proposes the given slugs in turn.
+        struct Rigged(Vec<&'static str>);
+
+        impl SlugGenerator for Rigged {
+            fn generate(&mut self, _now: SystemTime) -> Slug {
+                Slug::from(self.0.remove(0))
+            }
+        }
+
+        let lookups = |slugs| {
+            let mut service =
+                UrlShortenerService::new().with_slug_generator(Rigged(slugs)).with_word_filter(DefaultWordList, 4);
+            service.index.lookups.set(0);
+            let link = service.handle_create_short_link(Url::from("https://a.com"), None).unwrap();
+            (link.slug, service.index.lookups.get())
+        };
+
+        let (slug, clean) = lookups(vec!["k7Qp2"]);
+        assert_eq!(slug, Slug::from("k7Qp2"));
+        let (slug, retried) = lookups(vec!["Fuck", "k7Qp2"]);
+        assert_eq!(slug, Slug::from("k7Qp2"));
+        assert!(retried <= clean + 1, "{retried} lookups after one retry, {clean} without");
+    }
+
+    #[test]
+    fn redirects_look_up_the_slug_once() {
+        let slug = Slug::from("x".repeat(domain::MAX_SLUG_LEN));
+        let leniency = LookupLeniency { case_insensitive: true, ..LookupLeniency::default() };
+        let mut service = UrlShortenerService::new().with_snapshot_interval(2).with_lookup_leniency(leniency);
+        service.handle_create_short_link(Url::from("https://a.com"), Some(slug.clone())).unwrap();
+
+        // Exact, folded and missing slugs, saving a snapshot on the way.
+        for requested in [slug.clone(), Slug::from(slug.0.to_uppercase()), Slug::from("missing")] {
+            service.index.lookups.set(0);
+            let _ = service.handle_redirect(requested);
+            assert_eq!(service.index.lookups.get(), 1);
+        }
+
+        let id = service.slug_id(&slug).unwrap();
+        assert_eq!(service.index.snapshot(id).map(|snapshot| snapshot.version), Some(2));
+        assert_eq!(service.get_stats(slug).unwrap().redirects, 2);
+        assert_eq!(service.slug_id(&Slug::from("missing")), None);
+    }
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "extension")]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use url_shortener::commands::CommandHandler;
+use url_shortener::extension::ShortLinkAggregate;
+use url_shortener::queries::QueryHandler;
+use url_shortener::{EventType, ShortLink, ShortenerError, Slug, Url, UrlShortenerService};
+
+/// A command defined outside the crate: copies a link to a new slug with
+/// fresh stats, recording where the copy came from.
+fn handle_clone(service: &mut UrlShortenerService, from: &Slug, to: Slug) -> Result<ShortLink, ShortenerError> {
+    let mut source = ShortLinkAggregate::new(service);
+    source.rehydrate_by_slug(from);
+    let url = source.link().ok_or(ShortenerError::SlugNotFound)?.url.clone();
+
+    let mut clone = ShortLinkAggregate::new(service);
+    clone.rehydrate_by_slug(&to);
+    let link = clone.create_short_link(&url, None)?;
+    clone.emit_custom("cloned".to_string(), from.0.clone())?;
+
+    Ok(link)
+}
+
+type Clones = Rc<RefCell<Vec<(Slug, String)>>>;
+
+fn clone_projection(clones: &Clones) -> impl FnMut(&Slug, &str, &str) + 'static {
+    let clones = Rc::clone(clones);
+
+    move |slug, name, payload| {
+        if name == "cloned" {
+            clones.borrow_mut().push((slug.clone(), payload.to_string()));
+        }
+    }
+}
+
+#[test]
+fn custom_command_records_and_projects_its_events() {
+    let clones = Clones::default();
+    let mut service = UrlShortenerService::new()
+        .with_invariant_checks(1)
+        .with_custom_projection(clone_projection(&clones));
+
+    let original = Slug::from("original");
+    let copy = Slug::from("copy");
+    service.handle_create_short_link(Url::from("https://example.com"), Some(original.clone())).unwrap();
+    service.handle_redirect(original.clone()).unwrap();
+
+    let link = handle_clone(&mut service, &original, copy.clone()).unwrap();
+    assert_eq!(link.url, Url::from("https://example.com"));
+    assert_eq!(service.get_stats(copy.clone()).unwrap().redirects, 0);
+    assert_eq!(*clones.borrow(), [(copy.clone(), "original".to_string())]);
+
+    let events = service.get_events(&copy).unwrap();
+    assert!(matches!(
+        &events.last().unwrap().event_type,
+        EventType::Custom { name, payload } if name == "cloned" && payload == "original"
+    ));
+
+    assert_eq!(handle_clone(&mut service, &Slug::from("missing"), Slug::from("other")), Err(ShortenerError::SlugNotFound));
+    assert_eq!(handle_clone(&mut service, &original, copy), Err(ShortenerError::SlugAlreadyInUse));
+    assert_eq!(clones.borrow().len(), 1);
+}
+
+#[test]
+fn custom_events_need_an_existing_link() {
+    let mut service = UrlShortenerService::new().with_invariant_checks(1);
+
+    let result = service.emit_custom(Slug::from("missing"), "note".to_string(), String::new());
+
+    assert_eq!(result, Err(ShortenerError::SlugNotFound));
+    assert!(service.events_since(0).is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn custom_events_survive_export_and_import() {
+    let mut service = UrlShortenerService::new().with_invariant_checks(1);
+    let original = Slug::from("original");
+    service.handle_create_short_link(Url::from("https://example.com"), Some(original.clone())).unwrap();
+    handle_clone(&mut service, &original, Slug::from("copy")).unwrap();
+
+    let imported = UrlShortenerService::import_events_json(&service.export_events_json()).unwrap();
+    assert_eq!(imported.events_since(0), service.events_since(0));
+
+    // A projection registered later catches up on the recorded events.
+    let clones = Clones::default();
+    let _imported = imported.with_custom_projection(clone_projection(&clones));
+    assert_eq!(*clones.borrow(), [(Slug::from("copy"), "original".to_string())]);
+}